@@ -0,0 +1,426 @@
+use crate::scan::{self, ScanOptions};
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub generated_globs: Vec<String>,
+    pub test_globs: Vec<String>,
+    pub only_globs: Vec<String>,
+    pub max_file_size: Option<FileSize>,
+    pub max_file_sizes: BTreeMap<String, FileSize>,
+    pub max_depth: Option<usize>,
+    pub detect_encoding: bool,
+    pub prose_accurate: bool,
+    pub subprojects: Vec<PathBuf>,
+    pub detect_subprojects: bool,
+    pub follow_moves: bool,
+    pub analyze_terms: bool,
+    pub count_newline_as_char: bool,
+    pub normalize_trailing_newline: bool,
+    /// Extensions to track instead of the built-in list.
+    pub extensions: Vec<String>,
+    /// Globs for files and directories never to track.
+    pub ignore: Vec<String>,
+    /// Leave out files excluded by `.gitignore`. On unless set to `false`.
+    pub respect_gitignore: Option<bool>,
+    /// Shell command run after a session starts.
+    pub on_start: Option<String>,
+    /// End the new session again when the `on_start` hook fails, instead of
+    /// only warning.
+    pub abort_on_hook_failure: bool,
+    /// Sessions that added and removed fewer lines than this in total are
+    /// summarized as "minor changes".
+    pub minor_change_lines: Option<u32>,
+    /// Leave sessions with only minor changes out of the history.
+    pub skip_minor_sessions: bool,
+    /// External command whose JSON output is compared at the start and end
+    /// of a session, such as `cloc --json .`.
+    pub metric_cmd: Option<String>,
+    /// Path to the numbers to read in `metric_cmd`'s output.
+    pub metric_path: Option<String>,
+    /// Count code, comments and blanks per language with `tokei`.
+    pub tokei: bool,
+    /// A session with no activity for this long is considered abandoned.
+    pub session_timeout: Option<Timeout>,
+    /// Gaps between activity longer than this count as breaks rather than
+    /// active time.
+    pub idle_threshold: Option<Timeout>,
+    /// Record the host name, operating system and editor with each session.
+    pub record_machine: bool,
+    /// Flags added to each subcommand's command line, keyed by subcommand,
+    /// such as `end = ["--format", "markdown"]`. Flags given explicitly
+    /// override them.
+    pub default_flags: BTreeMap<String, Vec<String>>,
+}
+
+/// Settings for ego as a whole rather than one project, read from the global
+/// config file.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct GlobalConfig {
+    pub history_backend: HistoryBackend,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryBackend {
+    Jsonl,
+    /// Needs ego to be built with the `sqlite` feature, as it is by default.
+    Sqlite,
+}
+
+impl Default for HistoryBackend {
+    /// SQLite, unless ego was built without it.
+    fn default() -> Self {
+        if cfg!(feature = "sqlite") {
+            HistoryBackend::Sqlite
+        } else {
+            HistoryBackend::Jsonl
+        }
+    }
+}
+
+impl GlobalConfig {
+    pub fn load() -> Result<Self> {
+        let config_file = global_config_file()?;
+        if !config_file.exists() {
+            return Ok(GlobalConfig::default());
+        }
+
+        let contents = fs::read_to_string(&config_file)?;
+        toml::from_str(&contents)
+            .with_context(|| format!("invalid config file {}", config_file.display()))
+    }
+}
+
+/// Reads a config file, checking it on its own so an error names the file
+/// at fault. A missing file is empty.
+fn read_table(config_file: &Path) -> Result<toml::Table> {
+    if !config_file.exists() {
+        return Ok(toml::Table::new());
+    }
+
+    let table: toml::Table = toml::from_str(&fs::read_to_string(config_file)?)
+        .with_context(|| format!("invalid config file {}", config_file.display()))?;
+    table
+        .clone()
+        .try_into::<Config>()
+        .with_context(|| format!("invalid config file {}", config_file.display()))?;
+    Ok(table)
+}
+
+/// The directory holding ego's history.
+pub fn ego_dir() -> Result<PathBuf> {
+    Ok(home_dir()?.join(".ego"))
+}
+
+fn home_dir() -> Result<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+        .ok_or_else(|| anyhow!("could not determine home directory"))
+}
+
+/// The config file for every project, `$XDG_CONFIG_HOME/ego/config.toml`.
+pub fn global_config_file() -> Result<PathBuf> {
+    Ok(global_config_file_in(
+        env::var_os("XDG_CONFIG_HOME").map(PathBuf::from),
+        &home_dir()?,
+    ))
+}
+
+/// `config_home`, unless unset or relative as the XDG spec has it, defaults
+/// to `~/.config`. `~/.ego/config.toml`, where earlier versions kept the
+/// file, is read as long as there's none in the new place.
+fn global_config_file_in(config_home: Option<PathBuf>, home: &Path) -> PathBuf {
+    let config_home = config_home
+        .filter(|dir| dir.is_absolute())
+        .unwrap_or_else(|| home.join(".config"));
+    let config_file = config_home.join("ego").join("config.toml");
+    let legacy = home.join(".ego").join("config.toml");
+    if !config_file.exists() && legacy.exists() {
+        legacy
+    } else {
+        config_file
+    }
+}
+
+/// A byte count given either as a plain integer or as a string with a unit
+/// suffix, such as `"100KB"` or `"1.5MB"`.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(try_from = "SizeValue")]
+pub struct FileSize(pub u64);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SizeValue {
+    Bytes(u64),
+    Text(String),
+}
+
+impl TryFrom<SizeValue> for FileSize {
+    type Error = String;
+
+    fn try_from(value: SizeValue) -> Result<Self, Self::Error> {
+        match value {
+            SizeValue::Bytes(bytes) => Ok(FileSize(bytes)),
+            SizeValue::Text(text) => parse_size(&text).map(FileSize),
+        }
+    }
+}
+
+/// A length of time given as a string such as `"12h"`, in seconds.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Timeout(pub i64);
+
+impl TryFrom<String> for Timeout {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        parse_timeout(&text).map(Timeout)
+    }
+}
+
+impl Config {
+    pub fn file(project_directory: &Path) -> PathBuf {
+        project_directory.join(".ego.toml")
+    }
+
+    /// The project's `.ego.toml` on top of the defaults in the global config
+    /// file. A key set in both takes the project's value, lists included.
+    pub fn load(project_directory: &Path) -> Result<Self> {
+        let mut table = read_table(&global_config_file()?)?;
+        table.extend(read_table(&Self::file(project_directory))?);
+        Ok(table.try_into()?)
+    }
+
+    /// The keys set in the project's `.ego.toml`, to tell configured values
+    /// from defaults.
+    pub fn keys(project_directory: &Path) -> Result<BTreeSet<String>> {
+        Ok(read_table(&Self::file(project_directory))?
+            .keys()
+            .cloned()
+            .collect())
+    }
+
+    /// The keys set in the global config file.
+    pub fn global_keys() -> Result<BTreeSet<String>> {
+        Ok(read_table(&global_config_file()?)?
+            .keys()
+            .cloned()
+            .collect())
+    }
+
+    pub fn scan_options(&self) -> ScanOptions {
+        ScanOptions {
+            generated_globs: self.generated_globs.clone(),
+            only_globs: self.only_globs.clone(),
+            max_file_size: Some(
+                self.max_file_size
+                    .map_or(scan::DEFAULT_MAX_FILE_SIZE, |size| size.0),
+            ),
+            max_file_sizes: self
+                .max_file_sizes
+                .iter()
+                .map(|(ext, size)| (ext.trim_start_matches('.').to_lowercase(), size.0))
+                .collect(),
+            max_depth: self.max_depth,
+            detect_encoding: self.detect_encoding,
+            prose_accurate: self.prose_accurate,
+            subprojects: self.subprojects.clone(),
+            detect_subprojects: self.detect_subprojects,
+            follow_moves: self.follow_moves,
+            analyze_terms: self.analyze_terms,
+            count_newline_as_char: self.count_newline_as_char,
+            normalize_trailing_newline: self.normalize_trailing_newline,
+            test_globs: self.test_globs.clone(),
+            extensions: self.extensions.clone(),
+            ignore_globs: self.ignore.clone(),
+            respect_gitignore: self.respect_gitignore.unwrap_or(true),
+        }
+    }
+}
+
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid size: {}", text))?;
+    let multiplier = match unit.trim().to_uppercase().as_str() {
+        "" | "B" => 1.0,
+        "K" | "KB" | "KIB" => 1024.0,
+        "M" | "MB" | "MIB" => 1024.0 * 1024.0,
+        "G" | "GB" | "GIB" => 1024.0 * 1024.0 * 1024.0,
+        _ => return Err(format!("invalid size unit: {}", unit)),
+    };
+    Ok((number * multiplier) as u64)
+}
+
+/// Parses a billing increment such as `6m`, `15m`, `30m` or `1h` into
+/// seconds. A bare number is taken as minutes.
+pub fn parse_increment(text: &str) -> Result<i64, String> {
+    let units = [("s", 1), ("m", 60), ("min", 60), ("h", 3600)];
+    positive(
+        parse_duration(text, "increment", &units, "m")?,
+        "increment",
+        text,
+    )
+}
+
+/// Parses a ratio between 0 and 1, such as a similarity threshold.
+pub fn parse_ratio(text: &str) -> Result<f64, String> {
+    let ratio: f64 = text
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid ratio: {}", text))?;
+    if (0.0..=1.0).contains(&ratio) {
+        Ok(ratio)
+    } else {
+        Err(format!("ratio must be between 0 and 1: {}", text))
+    }
+}
+
+/// Parses a timeout such as `90m`, `12h` or `2d` into seconds. A bare number
+/// is taken as hours.
+pub fn parse_timeout(text: &str) -> Result<i64, String> {
+    let units = [("s", 1), ("m", 60), ("min", 60), ("h", 3600), ("d", 86400)];
+    positive(
+        parse_duration(text, "timeout", &units, "h")?,
+        "timeout",
+        text,
+    )
+}
+
+/// Parses an age such as `90d` or `12w` into a number of days. A bare number
+/// is taken as days.
+pub fn parse_age(text: &str) -> Result<i64, String> {
+    parse_duration(text, "age", &[("d", 1), ("w", 7)], "d")
+}
+
+/// Parses a whole number followed by one of `units`, given with its size in
+/// the unit the result is in, or by nothing for `default_unit`. `what` names
+/// the value in errors.
+fn parse_duration(
+    text: &str,
+    what: &str,
+    units: &[(&str, i64)],
+    default_unit: &str,
+) -> Result<i64, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+
+    let number: i64 = number
+        .parse()
+        .map_err(|_| format!("invalid {}: {}", what, text))?;
+    let unit = unit.trim().to_lowercase();
+    let unit = if unit.is_empty() { default_unit } else { &unit };
+    let (_, multiplier) = units
+        .iter()
+        .find(|(name, _)| *name == unit)
+        .ok_or_else(|| format!("invalid {} unit: {}", what, unit))?;
+    number
+        .checked_mul(*multiplier)
+        .ok_or_else(|| format!("{} is too long: {}", what, text))
+}
+
+fn positive(value: i64, what: &str, text: &str) -> Result<i64, String> {
+    if value > 0 {
+        Ok(value)
+    } else {
+        Err(format!("{} must be positive: {}", what, text.trim()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn durations_take_bare_numbers_in_default_unit() {
+        assert_eq!(parse_increment("15"), Ok(15 * 60));
+        assert_eq!(parse_timeout("12"), Ok(12 * 3600));
+        assert_eq!(parse_age("90"), Ok(90));
+        assert_eq!(parse_increment(" 30S "), Ok(30));
+        assert_eq!(parse_timeout("90min"), Ok(90 * 60));
+        assert_eq!(parse_age("2w"), Ok(14));
+    }
+
+    #[test]
+    fn durations_reject_unknown_units() {
+        assert_eq!(
+            parse_increment("1d"),
+            Err("invalid increment unit: d".to_string())
+        );
+        assert_eq!(
+            parse_timeout("2y"),
+            Err("invalid timeout unit: y".to_string())
+        );
+        assert_eq!(parse_age("3h"), Err("invalid age unit: h".to_string()));
+        assert!(parse_timeout("h").is_err());
+        assert!(parse_timeout("").is_err());
+    }
+
+    #[test]
+    fn durations_reject_zero_and_negative_values() {
+        assert_eq!(
+            parse_increment("0m"),
+            Err("increment must be positive: 0m".to_string())
+        );
+        assert!(parse_timeout("0").is_err());
+        assert!(parse_increment("-5m").is_err());
+        assert!(parse_timeout("-1h").is_err());
+        assert!(parse_age("-3d").is_err());
+        // Pruning everything finished before now is a valid age.
+        assert_eq!(parse_age("0"), Ok(0));
+    }
+
+    #[test]
+    fn durations_reject_overflow() {
+        assert!(parse_timeout(&format!("{}d", i64::MAX)).is_err());
+    }
+
+    #[test]
+    fn global_config_follows_xdg_config_home() {
+        let home = TempDir::new();
+        let xdg = home.path().join("xdg");
+        assert_eq!(
+            global_config_file_in(Some(xdg.clone()), home.path()),
+            xdg.join("ego/config.toml")
+        );
+        assert_eq!(
+            global_config_file_in(None, home.path()),
+            home.path().join(".config/ego/config.toml")
+        );
+        // Relative values are to be ignored.
+        assert_eq!(
+            global_config_file_in(Some(PathBuf::from("xdg")), home.path()),
+            home.path().join(".config/ego/config.toml")
+        );
+    }
+
+    #[test]
+    fn global_config_falls_back_to_legacy_location() {
+        let home = TempDir::new();
+        let legacy = home.write(".ego/config.toml", "tokei = true\n");
+        assert_eq!(global_config_file_in(None, home.path()), legacy);
+
+        let current = home.write(".config/ego/config.toml", "tokei = true\n");
+        assert_eq!(global_config_file_in(None, home.path()), current);
+    }
+}
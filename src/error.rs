@@ -0,0 +1,56 @@
+use chrono::{DateTime, Local};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+
+/// Failures of the core session operations that callers may want to handle
+/// individually rather than just report.
+#[derive(Debug, Error)]
+pub enum EgoError {
+    #[error("no active session found")]
+    NoSession,
+    #[error(
+        "a session started at {} is already active in {}",
+        started.format("%Y-%m-%d %H:%M:%S"),
+        directory.display()
+    )]
+    SessionActive {
+        directory: PathBuf,
+        started: DateTime<Local>,
+    },
+    #[error("session file {} is corrupt", path.display())]
+    CorruptSession {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("project directory {} does not exist", .0.display())]
+    DirectoryMissing(PathBuf),
+    #[error("scanning the project took longer than {}s", .0.as_secs())]
+    ScanTimeout(Duration),
+    #[error("permission denied: {}", path.display())]
+    PermissionDenied {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("failed to access {}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+}
+
+impl EgoError {
+    /// Wraps an I/O error on `path`, singling out permission problems.
+    pub fn io(path: &Path, source: io::Error) -> Self {
+        let path = path.to_path_buf();
+        if source.kind() == io::ErrorKind::PermissionDenied {
+            EgoError::PermissionDenied { path, source }
+        } else {
+            EgoError::Io { path, source }
+        }
+    }
+}
@@ -0,0 +1,360 @@
+use crate::history::HistoryEntry;
+use crate::session::ChangeKind;
+use crate::stats::{self, SessionStats, StatsFormatter};
+use anyhow::Result;
+use clap::ValueEnum;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    Toggl,
+}
+
+/// Writes `entries` in the given format. With `round_active`, durations are
+/// the session's active time rounded up to that many seconds instead of the
+/// wall-clock span.
+pub fn export<W: Write>(
+    entries: &[HistoryEntry],
+    format: ExportFormat,
+    round_active: Option<i64>,
+    out: &mut W,
+) -> Result<()> {
+    match format {
+        ExportFormat::Toggl => write_toggl_csv(entries, round_active, out),
+    }
+}
+
+/// Writes sessions using Toggl Track's CSV import columns:
+///
+/// | Toggl column  | Source                                      |
+/// |---------------|---------------------------------------------|
+/// | `Start date`  | session start, `YYYY-MM-DD`                 |
+/// | `Start time`  | session start, `HH:MM:SS`                   |
+/// | `End date`    | session end, `YYYY-MM-DD`                   |
+/// | `End time`    | session end, `HH:MM:SS`                     |
+/// | `Duration`    | end minus start, or rounded active time     |
+/// | `Project`     | project name (defaults to the directory)    |
+/// | `Description` | summary of the lines written in the session |
+fn write_toggl_csv<W: Write>(
+    entries: &[HistoryEntry],
+    round_active: Option<i64>,
+    out: &mut W,
+) -> Result<()> {
+    writeln!(
+        out,
+        "Start date,Start time,End date,End time,Duration,Project,Description"
+    )?;
+
+    for entry in entries {
+        let duration_secs = match round_active {
+            Some(increment) => stats::round_up(entry.active_time_seconds, increment),
+            None => entry.duration_secs(),
+        };
+        let description = format!(
+            "ego session: {}{} lines",
+            if entry.lines_written >= 0 { "+" } else { "" },
+            entry.lines_written
+        );
+
+        writeln!(
+            out,
+            "{},{},{},{},{:02}:{:02}:{:02},{},{}",
+            entry.start_time.format("%Y-%m-%d"),
+            entry.start_time.format("%H:%M:%S"),
+            entry.end_time.format("%Y-%m-%d"),
+            entry.end_time.format("%H:%M:%S"),
+            duration_secs / 3600,
+            (duration_secs % 3600) / 60,
+            duration_secs % 60,
+            csv_field(&entry.project_name),
+            csv_field(&description)
+        )?;
+    }
+    Ok(())
+}
+
+/// Appends a one-line summary of the session to a running log such as a
+/// `WORKLOG.md`. Each line carries a hidden marker with the session's start
+/// time, so appending the same session twice has no effect.
+pub fn append_summary(path: &Path, stats: &SessionStats, formatter: &StatsFormatter) -> Result<()> {
+    let marker = format!("<!-- ego:{} -->", stats.start_time.to_rfc3339());
+    if path.exists() && fs::read_to_string(path)?.contains(&marker) {
+        return Ok(());
+    }
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "- {} {}", formatter.summary(stats), marker)?;
+    Ok(())
+}
+
+pub fn write_file_changes_csv<W: Write>(stats: &SessionStats, out: &mut W) -> Result<()> {
+    writeln!(out, "path,change,initial_lines,final_lines,delta")?;
+    for change in &stats.file_changes {
+        writeln!(
+            out,
+            "{},{},{},{},{}",
+            csv_field(&stats.relative_path(&change.path).to_string_lossy()),
+            match change.kind {
+                ChangeKind::Created => "created",
+                ChangeKind::Modified => "modified",
+                ChangeKind::Deleted => "deleted",
+                ChangeKind::Renamed => "renamed",
+            },
+            change.initial_lines,
+            change.final_lines,
+            change.delta()
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the end-of-session report as Markdown, for pasting into pull
+/// requests and issues.
+pub fn write_markdown<W: Write>(
+    stats: &SessionStats,
+    formatter: &StatsFormatter,
+    out: &mut W,
+) -> Result<()> {
+    writeln!(
+        out,
+        "# {} – {}",
+        stats.project_name,
+        formatter.short_duration(stats.duration_secs)
+    )?;
+    writeln!(out)?;
+    writeln!(out, "| Stat | Value |")?;
+    writeln!(out, "|---|---|")?;
+    let mut rows = vec![
+        ("Started", formatter.datetime(&stats.start_time)),
+        ("Ended", formatter.datetime(&stats.end_time)),
+        ("Duration", formatter.duration(stats.duration_secs)),
+        ("Active time", formatter.duration(stats.active_secs)),
+        ("Idle time", formatter.duration(stats.idle_secs())),
+        ("Breaks", stats.break_durations.len().to_string()),
+        ("Initial lines", formatter.count(stats.initial_line_count)),
+        ("Final lines", formatter.count(stats.final_line_count)),
+        if stats.is_cleanup() {
+            (
+                "Lines removed",
+                format!("{} (cleanup)", formatter.count(-stats.lines_written)),
+            )
+        } else {
+            ("Lines written", formatter.lines_written(stats))
+        },
+        ("Lines per hour", formatter.rate(stats.lines_per_hour())),
+        (
+            "Files per active hour",
+            formatter.rate(stats.files_per_active_hour()),
+        ),
+    ];
+    for goal in formatter.goals(stats) {
+        rows.push((goal.title, goal.progress));
+    }
+    if stats.paused_secs > 0 {
+        rows.push(("Paused time", formatter.duration(stats.paused_secs)));
+    }
+    if let Some(touched) = stats.files_touched {
+        rows.push(("Files worked on", formatter.count(touched as i32)));
+    }
+    if stats.files_skipped > 0 {
+        rows.push((
+            "Files skipped (too large or binary)",
+            formatter.count(stats.files_skipped as i32),
+        ));
+    }
+    if stats.test_lines_written != 0 {
+        rows.push((
+            "Production lines written",
+            formatter.signed_count(stats.production_lines_written()),
+        ));
+        rows.push((
+            "Test lines written",
+            formatter.signed_count(stats.test_lines_written),
+        ));
+    }
+    if let Some(comparison) = formatter.history_comparison(stats) {
+        rows.push(("Compared to history", comparison));
+    }
+    if let Some(secs) = stats.time_to_first_edit_secs {
+        rows.push(("Time to first edit", formatter.duration(secs)));
+    }
+    if let Some(mood) = stats.mood {
+        rows.push(("Mood", format!("{}/5", mood)));
+    }
+    if let Some(energy) = stats.energy {
+        rows.push(("Energy", format!("{}/5", energy)));
+    }
+    if let Some(machine) = &stats.machine {
+        if let Some(host) = &machine.host {
+            rows.push(("Host", host.clone()));
+        }
+        rows.push(("OS", machine.os.clone()));
+        if let Some(editor) = &machine.editor {
+            rows.push(("Editor", editor.clone()));
+        }
+    }
+    if let (Some(initial), Some(last)) = (stats.initial_prose_words, stats.final_prose_words) {
+        rows.push((
+            "Prose words written",
+            formatter.signed_count(last - initial),
+        ));
+    }
+    for (label, value) in rows {
+        writeln!(out, "| {} | {} |", label, value)?;
+    }
+
+    for (label, files) in [
+        ("Created", &stats.files_created),
+        ("Modified", &stats.files_modified),
+        ("Deleted", &stats.files_deleted),
+    ] {
+        if files.is_empty() {
+            continue;
+        }
+        writeln!(out)?;
+        writeln!(out, "## {} ({})", label, files.len())?;
+        writeln!(out)?;
+        for file in files {
+            writeln!(out, "- `{}`", formatter.path(stats.relative_path(file)))?;
+        }
+    }
+
+    if !stats.files_renamed.is_empty() {
+        writeln!(out)?;
+        writeln!(out, "## Renamed ({})", stats.files_renamed.len())?;
+        writeln!(out)?;
+        for rename in &stats.files_renamed {
+            writeln!(out, "- {}", formatter.rename(stats, rename))?;
+        }
+    }
+
+    if !stats.metrics_shown.is_empty() {
+        writeln!(out)?;
+        writeln!(out, "## Metrics")?;
+        writeln!(out)?;
+        writeln!(out, "| Metric | Change |")?;
+        writeln!(out, "|---|---|")?;
+        for metric in &stats.metrics_shown {
+            writeln!(
+                out,
+                "| {} | {} |",
+                metric.label(),
+                formatter.metric_change(stats, *metric)
+            )?;
+        }
+    }
+
+    if let Some(metric) = &stats.command_metric {
+        let changes = metric.changes();
+        if !changes.is_empty() {
+            writeln!(out)?;
+            writeln!(out, "## `{}`", metric.command)?;
+            writeln!(out)?;
+            writeln!(out, "| Value | Change |")?;
+            writeln!(out, "|---|---|")?;
+            for (name, initial, last) in changes {
+                writeln!(
+                    out,
+                    "| {} | {} |",
+                    name,
+                    formatter.command_metric_change(initial, last)
+                )?;
+            }
+        }
+    }
+
+    if let Some(counts) = &stats.tokei {
+        let languages = counts.languages();
+        if !languages.is_empty() {
+            writeln!(out)?;
+            writeln!(out, "## Code (tokei)")?;
+            writeln!(out)?;
+            writeln!(out, "| Language | Code | Comments | Blanks |")?;
+            writeln!(out, "|---|---:|---:|---:|")?;
+            for (language, initial, last) in languages {
+                writeln!(
+                    out,
+                    "| {} | {} | {} | {} |",
+                    language,
+                    formatter.count_with_change(initial.code, last.code),
+                    formatter.count_with_change(initial.comments, last.comments),
+                    formatter.count_with_change(initial.blanks, last.blanks)
+                )?;
+            }
+        }
+    }
+
+    if !stats.top_terms.is_empty() {
+        writeln!(out)?;
+        writeln!(out, "## Top terms")?;
+        writeln!(out)?;
+        writeln!(out, "{}", formatter.top_terms(&stats.top_terms))?;
+    }
+
+    for (title, moves) in [
+        ("Moved out of scope", &stats.files_moved_out),
+        ("Moved into scope", &stats.files_moved_in),
+    ] {
+        if moves.is_empty() {
+            continue;
+        }
+        writeln!(out)?;
+        writeln!(out, "## {} ({})", title, moves.len())?;
+        writeln!(out)?;
+        for moved in moves {
+            writeln!(out, "- {}", formatter.rename(stats, moved))?;
+        }
+    }
+
+    for subproject in &stats.subprojects {
+        writeln!(out)?;
+        writeln!(out, "## Subproject `{}`", formatter.path(&subproject.path))?;
+        writeln!(out)?;
+        writeln!(
+            out,
+            "- Lines: {} → {} ({})",
+            formatter.count(subproject.initial_line_count),
+            formatter.count(subproject.final_line_count),
+            formatter.signed_count(subproject.lines_written())
+        )?;
+        writeln!(
+            out,
+            "- Files changed: {}",
+            formatter.count(subproject.files_changed as i32)
+        )?;
+    }
+
+    let breakdown = stats.breakdown();
+    if !breakdown.is_empty() {
+        writeln!(out)?;
+        writeln!(out, "## {}", stats.group_by.heading())?;
+        writeln!(out)?;
+        writeln!(
+            out,
+            "| {} | Files | Lines | Chars |",
+            stats.group_by.label()
+        )?;
+        writeln!(out, "|---|---:|---:|---:|")?;
+        for (group, change) in breakdown {
+            writeln!(
+                out,
+                "| {} | {} | {} | {} |",
+                group,
+                formatter.count(change.files as i32),
+                formatter.signed_count(change.lines),
+                formatter.signed_count(change.chars)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,119 @@
+use crate::scan::{self, FileFilter, ScanOptions};
+use anyhow::{anyhow, Context, Result};
+use git2::{ObjectType, Patch, Repository, TreeWalkMode, TreeWalkResult};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How the working tree differs from the tip of another branch, limited to
+/// the files a scan of the project directory would track.
+#[derive(Serialize, Deserialize)]
+pub struct BranchComparison {
+    pub branch: String,
+    pub files_added: usize,
+    pub files_changed: usize,
+    pub files_removed: usize,
+    pub lines_added: usize,
+    pub lines_removed: usize,
+}
+
+pub fn compare_branch(
+    project_directory: &Path,
+    options: &ScanOptions,
+    branch: &str,
+) -> Result<BranchComparison> {
+    let repo = Repository::discover(project_directory)
+        .with_context(|| format!("{} is not in a git repository", project_directory.display()))?;
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| anyhow!("cannot compare against a branch in a bare repository"))?
+        .canonicalize()?;
+    let tree = repo
+        .revparse_single(branch)
+        .and_then(|object| object.peel_to_tree())
+        .with_context(|| format!("branch {} not found", branch))?;
+
+    let root = fs::canonicalize(project_directory)?;
+    let prefix = root.strip_prefix(&workdir).unwrap_or(Path::new(""));
+    let generated = options.generated_matcher()?;
+    let filter = FileFilter::new(project_directory, options)?;
+    let in_scope = |relative: &Path| {
+        filter.tracks(relative) && options.within_depth(relative) && !generated.is_match(relative)
+    };
+
+    let mut branch_files: BTreeMap<PathBuf, Vec<u8>> = BTreeMap::new();
+    tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+        if entry.kind() != Some(ObjectType::Blob) {
+            return TreeWalkResult::Ok;
+        }
+        let Some(name) = entry.name() else {
+            return TreeWalkResult::Ok;
+        };
+        let repo_path = Path::new(dir).join(name);
+        let Ok(relative) = repo_path.strip_prefix(prefix) else {
+            return TreeWalkResult::Ok;
+        };
+        if in_scope(relative) {
+            if let Ok(blob) = entry
+                .to_object(&repo)
+                .and_then(|object| object.peel_to_blob())
+            {
+                branch_files.insert(relative.to_path_buf(), blob.content().to_vec());
+            }
+        }
+        TreeWalkResult::Ok
+    })?;
+
+    let mut comparison = BranchComparison {
+        branch: branch.to_string(),
+        files_added: 0,
+        files_changed: 0,
+        files_removed: 0,
+        lines_added: 0,
+        lines_removed: 0,
+    };
+
+    let current = scan::scan_files(project_directory, options)?;
+    let mut seen = BTreeSet::new();
+    for path in current.files.keys() {
+        let relative = path.strip_prefix(project_directory).unwrap_or(path);
+        if !in_scope(relative) {
+            continue;
+        }
+        let content = fs::read(path)?;
+        let old = branch_files.get(relative);
+        if old.is_some() {
+            seen.insert(relative.to_path_buf());
+        }
+        if old.is_some_and(|old| *old == content) {
+            continue;
+        }
+
+        let (additions, deletions) =
+            line_stats(relative, old.map_or(&[], Vec::as_slice), &content)?;
+        comparison.lines_added += additions;
+        comparison.lines_removed += deletions;
+        if old.is_some() {
+            comparison.files_changed += 1;
+        } else {
+            comparison.files_added += 1;
+        }
+    }
+
+    for (path, content) in &branch_files {
+        if !seen.contains(path) {
+            let (_, deletions) = line_stats(path, content, &[])?;
+            comparison.files_removed += 1;
+            comparison.lines_removed += deletions;
+        }
+    }
+
+    Ok(comparison)
+}
+
+fn line_stats(path: &Path, old: &[u8], new: &[u8]) -> Result<(usize, usize)> {
+    let patch = Patch::from_buffers(old, Some(path), new, Some(path), None)?;
+    let (_, additions, deletions) = patch.line_stats()?;
+    Ok((additions, deletions))
+}
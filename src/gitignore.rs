@@ -0,0 +1,143 @@
+//! `.gitignore` files read directly, for projects outside a git repository,
+//! where libgit2 has no repository to ask.
+
+use globset::{GlobBuilder, GlobMatcher};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+struct Rule {
+    glob: GlobMatcher,
+    negated: bool,
+    dir_only: bool,
+}
+
+/// The `.gitignore` files in a project, each read the first time a path
+/// below its directory is checked.
+pub struct GitignoreFiles {
+    root: PathBuf,
+    rules: RefCell<HashMap<PathBuf, Vec<Rule>>>,
+}
+
+impl GitignoreFiles {
+    pub fn new(root: &Path) -> Self {
+        GitignoreFiles {
+            root: root.to_path_buf(),
+            rules: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Whether a path, given relative to the project directory, is ignored.
+    /// As in git, a file in an ignored directory stays ignored even if a
+    /// pattern would include it again.
+    pub fn is_ignored(&self, relative: &Path, is_dir: bool) -> bool {
+        relative
+            .ancestors()
+            .skip(1)
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .any(|ancestor| self.decision(ancestor, true) == Some(true))
+            || self.decision(relative, is_dir) == Some(true)
+    }
+
+    /// The last rule matching `relative`, with the rules of deeper
+    /// `.gitignore` files coming after those of the directories above them.
+    fn decision(&self, relative: &Path, is_dir: bool) -> Option<bool> {
+        let mut decision = None;
+        let mut dirs: Vec<&Path> = relative.ancestors().skip(1).collect();
+        dirs.reverse();
+        let mut rules = self.rules.borrow_mut();
+        for dir in dirs {
+            let rules = rules
+                .entry(dir.to_path_buf())
+                .or_insert_with(|| read_rules(&self.root.join(dir).join(".gitignore")));
+            let within = relative.strip_prefix(dir).unwrap_or(relative);
+            for rule in rules.iter() {
+                if (is_dir || !rule.dir_only) && rule.glob.is_match(within) {
+                    decision = Some(!rule.negated);
+                }
+            }
+        }
+        decision
+    }
+}
+
+fn read_rules(file: &Path) -> Vec<Rule> {
+    fs::read_to_string(file)
+        .map(|text| text.lines().filter_map(parse_rule).collect())
+        .unwrap_or_default()
+}
+
+/// Parses one line of a `.gitignore` file. A pattern with a slash other than
+/// a trailing one is relative to the file's directory; one without matches
+/// at any depth. Lines git would reject are skipped.
+fn parse_rule(line: &str) -> Option<Rule> {
+    let line = line.trim_end();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let (negated, pattern) = match line.strip_prefix('!') {
+        Some(pattern) => (true, pattern),
+        None => (false, line.strip_prefix('\\').unwrap_or(line)),
+    };
+    let (dir_only, pattern) = match pattern.strip_suffix('/') {
+        Some(pattern) => (true, pattern),
+        None => (false, pattern),
+    };
+    let glob = if pattern.contains('/') {
+        pattern.trim_start_matches('/').to_string()
+    } else {
+        format!("**/{}", pattern)
+    };
+    let glob = GlobBuilder::new(&glob)
+        .literal_separator(true)
+        .build()
+        .ok()?
+        .compile_matcher();
+    Some(Rule {
+        glob,
+        negated,
+        dir_only,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn applies_nested_gitignore_files() {
+        let dir = TempDir::new();
+        dir.write(
+            ".gitignore",
+            "# build output\ntarget/\n*.log\n!keep.log\n/root_only.rs\n",
+        );
+        dir.write("src/.gitignore", "generated.rs\n");
+        let gitignore = GitignoreFiles::new(dir.path());
+        let ignored = |path: &str, is_dir| gitignore.is_ignored(Path::new(path), is_dir);
+
+        assert!(ignored("target", true));
+        assert!(ignored("target/debug/main.rs", false));
+        assert!(ignored("crates/core/target", true));
+        assert!(!ignored("target", false));
+        assert!(ignored("debug.log", false));
+        assert!(ignored("logs/debug.log", false));
+        assert!(!ignored("keep.log", false));
+        assert!(ignored("root_only.rs", false));
+        assert!(!ignored("src/root_only.rs", false));
+        assert!(ignored("src/generated.rs", false));
+        assert!(ignored("src/nested/generated.rs", false));
+        assert!(!ignored("generated.rs", false));
+        assert!(!ignored("src/main.rs", false));
+    }
+
+    #[test]
+    fn negation_cannot_reinclude_below_ignored_directory() {
+        let dir = TempDir::new();
+        dir.write(".gitignore", "vendor/\n!vendor/patched.rs\n");
+        let gitignore = GitignoreFiles::new(dir.path());
+
+        assert!(gitignore.is_ignored(Path::new("vendor/patched.rs"), false));
+    }
+}
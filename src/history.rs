@@ -0,0 +1,370 @@
+use crate::config::{self, GlobalConfig, HistoryBackend};
+use crate::machine::Machine;
+use crate::session::{self, FileChange, Session};
+use crate::sqlite;
+use crate::stats::{line_totals, SessionStats};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, TimeDelta};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// How far back the average a session is compared against reaches.
+pub const AVERAGE_WINDOW_DAYS: i64 = 30;
+
+#[derive(Serialize, Deserialize)]
+pub struct HistoryEntry {
+    #[serde(default)]
+    pub id: String,
+    pub start_time: DateTime<Local>,
+    pub end_time: DateTime<Local>,
+    pub project_directory: PathBuf,
+    #[serde(default)]
+    pub project_name: String,
+    pub initial_line_count: i32,
+    pub final_line_count: i32,
+    pub lines_written: i32,
+    /// Entries recorded before added lines were counted have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lines_added: Option<i32>,
+    #[serde(default)]
+    pub chars_written: i32,
+    #[serde(default)]
+    pub files_changed: usize,
+    #[serde(default)]
+    pub active_time_seconds: i64,
+    #[serde(default)]
+    pub paused_secs: i64,
+    #[serde(default)]
+    pub activity_samples: Vec<DateTime<Local>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mood: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub energy: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub machine: Option<Machine>,
+    /// Only kept by the SQLite store, and not loaded back from it.
+    #[serde(skip)]
+    #[cfg_attr(not(feature = "sqlite"), allow(dead_code))]
+    pub file_changes: Vec<FileChange>,
+}
+
+impl HistoryEntry {
+    pub fn new(session: &Session, end_time: DateTime<Local>) -> Self {
+        HistoryEntry {
+            id: session.id.clone(),
+            start_time: session.start_time,
+            end_time,
+            project_directory: fs::canonicalize(&session.project_directory)
+                .unwrap_or_else(|_| session.project_directory.clone()),
+            project_name: session.project_name.clone(),
+            initial_line_count: session.initial_line_count,
+            final_line_count: session.final_line_count.unwrap_or(0),
+            lines_written: session.lines_written.unwrap_or(0),
+            lines_added: Some(line_totals(&session.file_changes).0),
+            chars_written: session
+                .final_metrics
+                .map_or(0, |last| last.chars - session.initial_metrics.chars),
+            files_changed: session.file_changes.len(),
+            active_time_seconds: session.active_time_seconds,
+            paused_secs: session.paused_secs_at(end_time),
+            activity_samples: session.activity_samples.clone(),
+            mood: session.mood,
+            energy: session.energy,
+            machine: session.machine.clone(),
+            file_changes: session.file_changes.clone(),
+        }
+    }
+
+    /// Time from start to end, less time spent paused.
+    pub fn duration_secs(&self) -> i64 {
+        ((self.end_time - self.start_time).num_seconds() - self.paused_secs).max(0)
+    }
+}
+
+/// Lines added per hour of active time across the entries that ended
+/// since `since`, or `None` without any tracked active time to average.
+pub fn average_lines_per_active_hour(
+    entries: &[HistoryEntry],
+    since: DateTime<Local>,
+) -> Option<f64> {
+    let (lines, active_secs) = entries
+        .iter()
+        .filter(|entry| entry.end_time >= since && entry.active_time_seconds > 0)
+        .fold((0i64, 0i64), |(lines, secs), entry| {
+            (
+                lines + i64::from(entry.lines_added.unwrap_or(entry.lines_written)),
+                secs + entry.active_time_seconds,
+            )
+        });
+    (active_secs > 0).then(|| lines as f64 * 3600.0 / active_secs as f64)
+}
+
+/// Where the full stats of a finished session are kept for `ego open`.
+fn report_file(id: &str) -> Result<PathBuf> {
+    Ok(config::ego_dir()?
+        .join("reports")
+        .join(format!("{}.json", id)))
+}
+
+pub fn save_report(stats: &SessionStats) -> Result<()> {
+    let path = report_file(&stats.id)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string(stats)?)?;
+    Ok(())
+}
+
+/// The saved stats of the session with the given ID, or `None` for sessions
+/// ended before reports were saved.
+pub fn load_report(id: &str) -> Result<Option<SessionStats>> {
+    let path = report_file(id)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = fs::read_to_string(&path)?;
+    let stats = serde_json::from_str(&contents)
+        .with_context(|| format!("corrupt report {}", path.display()))?;
+    Ok(Some(stats))
+}
+
+pub fn history_file() -> Result<PathBuf> {
+    Ok(config::ego_dir()?.join("history.jsonl"))
+}
+
+pub fn sqlite_file() -> Result<PathBuf> {
+    Ok(config::ego_dir()?.join("history.db"))
+}
+
+/// Where finished sessions are recorded with the configured backend.
+pub fn store_location() -> Result<(HistoryBackend, PathBuf)> {
+    let backend = GlobalConfig::load()?.history_backend;
+    let path = match backend {
+        HistoryBackend::Jsonl => history_file()?,
+        HistoryBackend::Sqlite => sqlite_file()?,
+    };
+    Ok((backend, path))
+}
+
+/// Opens the SQLite store unless the global config file selects the JSONL
+/// file with `history_backend = "jsonl"`. A new database starts out with the
+/// sessions already in the JSONL history.
+fn open_sqlite() -> Result<Option<sqlite::Store>> {
+    match GlobalConfig::load()?.history_backend {
+        HistoryBackend::Jsonl => Ok(None),
+        #[cfg(feature = "sqlite")]
+        HistoryBackend::Sqlite => {
+            let dir = config::ego_dir()?;
+            fs::create_dir_all(&dir)?;
+            let path = sqlite_file()?;
+            let created = !path.exists();
+            let mut store = sqlite::Store::open(&path)?;
+            if created {
+                import_jsonl(&mut store)?;
+            }
+            Ok(Some(store))
+        }
+        #[cfg(not(feature = "sqlite"))]
+        HistoryBackend::Sqlite => {
+            anyhow::bail!(
+                "history_backend is set to sqlite, but ego was built without the sqlite feature"
+            )
+        }
+    }
+}
+
+/// Copies the sessions in the JSONL history into a new SQLite store. The
+/// JSONL file is left as it is, so switching back to it loses nothing
+/// recorded before the switch.
+#[cfg(feature = "sqlite")]
+fn import_jsonl(store: &mut sqlite::Store) -> Result<()> {
+    let entries = load_jsonl(None)?;
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let mut imported = 0;
+    for entry in &entries {
+        match store.append(entry) {
+            Ok(()) => imported += 1,
+            Err(err) => eprintln!(
+                "Warning: could not import session {} into the history database: {:#}",
+                entry.id, err
+            ),
+        }
+    }
+    eprintln!(
+        "Imported {} sessions from {} into {}.",
+        imported,
+        history_file()?.display(),
+        sqlite_file()?.display()
+    );
+    Ok(())
+}
+
+pub fn append(entry: &HistoryEntry) -> Result<()> {
+    if let Some(mut store) = open_sqlite()? {
+        return store.append(entry);
+    }
+
+    let path = history_file()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    Ok(())
+}
+
+pub fn load() -> Result<Vec<HistoryEntry>> {
+    load_since(None)
+}
+
+/// Entries for sessions that started at or after `since`, or all entries.
+/// The SQLite store filters in the query; the JSONL file is read in full.
+pub fn load_since(since: Option<DateTime<Local>>) -> Result<Vec<HistoryEntry>> {
+    if let Some(store) = open_sqlite()? {
+        let mut entries = store.load(since)?;
+        for entry in &mut entries {
+            let location = format!("for session {}", entry.id);
+            repair(entry, &location);
+        }
+        return Ok(entries);
+    }
+    load_jsonl(since)
+}
+
+fn load_jsonl(since: Option<DateTime<Local>>) -> Result<Vec<HistoryEntry>> {
+    let path = history_file()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut entries = Vec::new();
+    for (number, line) in fs::read_to_string(&path)?.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<HistoryEntry>(line) {
+            Ok(mut entry) => {
+                if since.is_some_and(|since| entry.start_time < since) {
+                    continue;
+                }
+                repair(&mut entry, &format!("on line {}", number + 1));
+                entries.push(entry)
+            }
+            Err(err) => eprintln!(
+                "Warning: skipping corrupt history entry on line {}: {}",
+                number + 1,
+                err
+            ),
+        }
+    }
+    Ok(entries)
+}
+
+/// Fills in fields missing from older entries and caps an active time that
+/// exceeds the session's span, warning about the entry at `location`.
+fn repair(entry: &mut HistoryEntry, location: &str) {
+    if entry.id.is_empty() {
+        entry.id = session::session_id(&entry.start_time);
+    }
+    let span = entry.duration_secs();
+    if entry.active_time_seconds > span || entry.active_time_seconds < 0 {
+        eprintln!(
+            "Warning: history entry {} recorded {}s of active time in {}s; capping it",
+            location, entry.active_time_seconds, span
+        );
+        entry.active_time_seconds = entry.active_time_seconds.clamp(0, span);
+    }
+    if entry.project_name.is_empty() {
+        entry.project_name = session::default_project_name(&entry.project_directory);
+    }
+}
+
+/// Which entries `prune` removes. With both limits, entries older than
+/// `older_than` are removed unless they are among the `keep_last` most
+/// recent; with only `keep_last`, everything else is removed.
+pub struct PruneOptions {
+    pub older_than: Option<TimeDelta>,
+    pub keep_last: Option<usize>,
+    pub dry_run: bool,
+}
+
+/// Removes old entries from the history, along with their saved reports, and
+/// returns them.
+pub fn prune(options: &PruneOptions, now: DateTime<Local>) -> Result<Vec<HistoryEntry>> {
+    let removed = match open_sqlite()? {
+        Some(mut store) => store.prune(options, now)?,
+        None => prune_jsonl(options, now)?,
+    };
+    if !options.dry_run {
+        for entry in &removed {
+            match fs::remove_file(report_file(&entry.id)?) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            }
+        }
+    }
+    Ok(removed)
+}
+
+/// Prunes the JSONL history. The file is rewritten through a temporary file
+/// and a rename, so an interrupted prune leaves the history intact. Corrupt
+/// lines are kept as they are.
+fn prune_jsonl(options: &PruneOptions, now: DateTime<Local>) -> Result<Vec<HistoryEntry>> {
+    let path = history_file()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let contents = fs::read_to_string(&path)?;
+    let lines: Vec<&str> = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect();
+    let entries: Vec<Option<HistoryEntry>> = lines
+        .iter()
+        .map(|line| serde_json::from_str(line).ok())
+        .collect();
+
+    let valid = entries.iter().filter(|entry| entry.is_some()).count();
+    let protected_from = valid.saturating_sub(options.keep_last.unwrap_or(0));
+    let mut position = 0;
+    let remove: Vec<bool> = entries
+        .iter()
+        .map(|entry| {
+            let Some(entry) = entry else {
+                return false;
+            };
+            position += 1;
+            if options.keep_last.is_some() && position > protected_from {
+                return false;
+            }
+            options
+                .older_than
+                .is_none_or(|age| entry.end_time < now - age)
+        })
+        .collect();
+
+    let mut kept = String::new();
+    let mut removed = Vec::new();
+    for ((line, entry), remove) in lines.iter().zip(entries).zip(remove) {
+        match entry {
+            Some(entry) if remove => removed.push(entry),
+            _ => {
+                kept.push_str(line);
+                kept.push('\n');
+            }
+        }
+    }
+
+    if !options.dry_run && !removed.is_empty() {
+        let temp = path.with_extension("jsonl.tmp");
+        fs::write(&temp, kept)?;
+        fs::rename(&temp, &path)?;
+    }
+    Ok(removed)
+}
@@ -0,0 +1,50 @@
+use crate::session::Session;
+use crate::stats::StatusReport;
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Runs a user-supplied shell command for a session event such as `start`.
+///
+/// The command gets `EGO_EVENT`, `EGO_SESSION_ID`, `EGO_PROJECT_NAME` and
+/// `EGO_PROJECT_DIR` in its environment and the session's status as JSON on
+/// stdin. Its output goes straight to the terminal. A non-zero exit status is
+/// returned as an error.
+pub fn run(command: &str, event: &str, session: &Session) -> Result<()> {
+    let mut child = shell(command)
+        .env("EGO_EVENT", event)
+        .env("EGO_SESSION_ID", &session.id)
+        .env("EGO_PROJECT_NAME", &session.project_name)
+        .env("EGO_PROJECT_DIR", &session.project_directory)
+        .stdin(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to run on-{} hook `{}`", event, command))?;
+
+    let report = StatusReport::new(session, &session.initial_metrics, Local::now());
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that doesn't read its stdin closes the pipe early, which is
+        // not an error.
+        let _ = stdin.write_all(serde_json::to_string(&report)?.as_bytes());
+    }
+
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("on-{} hook `{}` failed with {}", event, command, status);
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+pub fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.args(["/C", command]);
+    shell
+}
+
+#[cfg(not(windows))]
+pub fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.args(["-c", command]);
+    shell
+}
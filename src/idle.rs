@@ -0,0 +1,74 @@
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+use std::process::Command;
+use std::time::Duration;
+
+/// Reports whether the user is away from the machine: the screen is locked or
+/// there has been no keyboard/mouse input for at least `threshold`. Returns
+/// `None` when the platform offers no way to tell, in which case callers fall
+/// back to the gap-between-activity heuristic.
+pub fn user_is_away(threshold: Duration) -> Option<bool> {
+    if screen_locked() == Some(true) {
+        return Some(true);
+    }
+    system_idle_time().map(|idle| idle >= threshold)
+}
+
+#[cfg(target_os = "macos")]
+fn system_idle_time() -> Option<Duration> {
+    let output = Command::new("ioreg")
+        .args(["-c", "IOHIDSystem", "-d", "4"])
+        .output()
+        .ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let nanos = stdout
+        .lines()
+        .find(|line| line.contains("\"HIDIdleTime\""))?
+        .rsplit('=')
+        .next()?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(Duration::from_nanos(nanos))
+}
+
+#[cfg(target_os = "linux")]
+fn system_idle_time() -> Option<Duration> {
+    std::env::var_os("DISPLAY")?;
+    let output = Command::new("xprintidle").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let millis = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(Duration::from_millis(millis))
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn system_idle_time() -> Option<Duration> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn screen_locked() -> Option<bool> {
+    let session_id = std::env::var("XDG_SESSION_ID").ok()?;
+    let output = Command::new("loginctl")
+        .args([
+            "show-session",
+            &session_id,
+            "--property=LockedHint",
+            "--value",
+        ])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim() == "yes")
+}
+
+#[cfg(not(target_os = "linux"))]
+fn screen_locked() -> Option<bool> {
+    None
+}
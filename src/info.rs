@@ -0,0 +1,166 @@
+//! The settings a session started in a project would use, and where each of
+//! them comes from, for `ego info`.
+
+use crate::scan::{self, ScanOptions};
+use std::collections::BTreeSet;
+
+/// The keys set in the global config file, `.ego.toml` and on the command
+/// line, to attribute each setting to the layer it came from.
+pub struct Sources<'a> {
+    pub global_keys: &'a BTreeSet<String>,
+    pub config_keys: &'a BTreeSet<String>,
+    pub flags: &'a BTreeSet<&'static str>,
+}
+
+impl Sources<'_> {
+    /// Flags override `.ego.toml`, which overrides the global config, except for lists, which they extend.
+    fn of(&self, key: &str, extends: bool) -> &'static str {
+        match (self.config_keys.contains(key), self.flags.contains(key)) {
+            (true, true) if extends => ".ego.toml + flag",
+            (_, true) => "flag",
+            (true, false) => ".ego.toml",
+            (false, false) if self.global_keys.contains(key) => "global config",
+            (false, false) => "default",
+        }
+    }
+}
+
+pub struct Setting {
+    pub name: &'static str,
+    pub value: String,
+    pub source: &'static str,
+}
+
+/// The effective scan settings in `options` and the idle threshold, with
+/// their sources.
+pub fn settings(options: &ScanOptions, idle_threshold: i64, sources: &Sources) -> Vec<Setting> {
+    let setting = |name: &'static str, value: String, source: &'static str| Setting {
+        name,
+        value,
+        source,
+    };
+    let flag =
+        |name: &'static str, value: bool| setting(name, value.to_string(), sources.of(name, false));
+
+    let tracked_files = if !options.only_globs.is_empty() {
+        setting(
+            "tracked extensions",
+            "any file matching only_globs".to_string(),
+            sources.of("only_globs", true),
+        )
+    } else if options.extensions.is_empty() {
+        setting(
+            "tracked extensions",
+            scan::TRACKED_EXTENSIONS.join(", "),
+            "built in",
+        )
+    } else {
+        setting(
+            "tracked extensions",
+            options.extensions.join(", "),
+            sources.of("extensions", false),
+        )
+    };
+    let test_globs = if options.test_globs.is_empty() {
+        setting("test_globs", scan::DEFAULT_TEST_GLOBS.join(", "), "default")
+    } else {
+        setting(
+            "test_globs",
+            options.test_globs.join(", "),
+            sources.of("test_globs", true),
+        )
+    };
+
+    vec![
+        tracked_files,
+        setting(
+            "only_globs",
+            list(&options.only_globs),
+            sources.of("only_globs", true),
+        ),
+        setting(
+            "generated_globs",
+            list(&options.generated_globs),
+            sources.of("generated_globs", true),
+        ),
+        test_globs,
+        setting(
+            "ignored",
+            format!(
+                "hidden directories, {}, {}, {}",
+                scan::SESSION_FILE,
+                scan::FINISHED_SESSION_FILE,
+                scan::SAVING_SESSION_FILE
+            ),
+            "built in",
+        ),
+        setting(
+            "ignore",
+            list(&options.ignore_globs),
+            sources.of("ignore", false),
+        ),
+        flag("respect_gitignore", options.respect_gitignore),
+        setting(
+            "max_file_size",
+            options
+                .max_file_size
+                .map_or("none".to_string(), |size| format!("{} bytes", size)),
+            sources.of("max_file_size", false),
+        ),
+        setting(
+            "max_file_sizes",
+            if options.max_file_sizes.is_empty() {
+                "(none)".to_string()
+            } else {
+                options
+                    .max_file_sizes
+                    .iter()
+                    .map(|(ext, size)| format!("{}: {} bytes", ext, size))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            },
+            sources.of("max_file_sizes", false),
+        ),
+        setting(
+            "max_depth",
+            options
+                .max_depth
+                .map_or("unlimited".to_string(), |depth| depth.to_string()),
+            sources.of("max_depth", false),
+        ),
+        setting(
+            "subprojects",
+            list(
+                &options
+                    .subprojects
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>(),
+            ),
+            sources.of("subprojects", true),
+        ),
+        flag("detect_subprojects", options.detect_subprojects),
+        flag("detect_encoding", options.detect_encoding),
+        flag("prose_accurate", options.prose_accurate),
+        flag("follow_moves", options.follow_moves),
+        flag("analyze_terms", options.analyze_terms),
+        flag("count_newline_as_char", options.count_newline_as_char),
+        flag(
+            "normalize_trailing_newline",
+            options.normalize_trailing_newline,
+        ),
+        setting(
+            "idle_threshold",
+            format!("{}s", idle_threshold),
+            sources.of("idle_threshold", false),
+        ),
+    ]
+}
+
+fn list(items: &[String]) -> String {
+    if items.is_empty() {
+        "(none)".to_string()
+    } else {
+        items.join(", ")
+    }
+}
@@ -0,0 +1,120 @@
+use chrono::{DateTime, Local};
+use std::env;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Locale {
+    thousands_separator: Option<char>,
+    decimal_separator: char,
+    datetime_format: &'static str,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Locale {
+            thousands_separator: None,
+            decimal_separator: '.',
+            datetime_format: "%Y-%m-%d %H:%M:%S",
+        }
+    }
+}
+
+impl Locale {
+    /// Parses a locale tag such as `de_DE.UTF-8`, `fr-FR` or `ja`. The special
+    /// value `system` resolves the locale from `LC_ALL`, `LC_NUMERIC` or `LANG`.
+    pub fn parse(tag: &str) -> Result<Self, String> {
+        if tag.eq_ignore_ascii_case("system") {
+            return Ok(Self::from_env());
+        }
+
+        Self::lookup(tag).ok_or_else(|| format!("unsupported locale: {}", tag))
+    }
+
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LC_NUMERIC", "LANG"]
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| Self::lookup(&value))
+            .unwrap_or_default()
+    }
+
+    fn lookup(tag: &str) -> Option<Self> {
+        let tag = tag.split(['.', '@']).next().unwrap_or_default();
+        let mut parts = tag.split(['_', '-']);
+        let language = parts.next().unwrap_or_default().to_lowercase();
+        let region = parts.next().unwrap_or_default().to_uppercase();
+
+        let (thousands_separator, decimal_separator, datetime_format) =
+            match (language.as_str(), region.as_str()) {
+                ("c" | "posix", _) => return Some(Self::default()),
+                ("en", "US" | "") => (Some(','), '.', "%m/%d/%Y %I:%M:%S %p"),
+                ("en", "CA" | "PH") => (Some(','), '.', "%Y-%m-%d %I:%M:%S %p"),
+                ("en", _) => (Some(','), '.', "%d/%m/%Y %H:%M:%S"),
+                ("de", "CH") => (Some('\''), '.', "%d.%m.%Y %H:%M:%S"),
+                ("de", _) => (Some('.'), ',', "%d.%m.%Y %H:%M:%S"),
+                ("fr", "CH") => (Some('\u{202f}'), ',', "%d.%m.%Y %H:%M:%S"),
+                ("fr", _) => (Some('\u{202f}'), ',', "%d/%m/%Y %H:%M:%S"),
+                ("es" | "it" | "pt", _) => (Some('.'), ',', "%d/%m/%Y %H:%M:%S"),
+                ("nl", _) => (Some('.'), ',', "%d-%m-%Y %H:%M:%S"),
+                ("da" | "nb" | "no" | "fi" | "cs" | "sk" | "pl" | "ru" | "uk", _) => {
+                    (Some('\u{a0}'), ',', "%d.%m.%Y %H:%M:%S")
+                }
+                ("sv", _) => (Some('\u{a0}'), ',', "%Y-%m-%d %H:%M:%S"),
+                ("ja" | "zh", _) => (Some(','), '.', "%Y/%m/%d %H:%M:%S"),
+                ("ko", _) => (Some(','), '.', "%Y. %m. %d. %H:%M:%S"),
+                ("hi", _) => (Some(','), '.', "%d/%m/%Y %H:%M:%S"),
+                _ => return None,
+            };
+
+        Some(Locale {
+            thousands_separator,
+            decimal_separator,
+            datetime_format,
+        })
+    }
+
+    pub fn format_int(&self, value: i64) -> String {
+        let digits = value.unsigned_abs().to_string();
+        let sign = if value < 0 { "-" } else { "" };
+
+        match self.thousands_separator {
+            Some(separator) => format!("{}{}", sign, group_digits(&digits, separator)),
+            None => format!("{}{}", sign, digits),
+        }
+    }
+
+    pub fn format_float(&self, value: f64, precision: usize) -> String {
+        let formatted = format!("{:.*}", precision, value.abs());
+        let (integer, fraction) = match formatted.split_once('.') {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (formatted.as_str(), None),
+        };
+
+        let integer = match self.thousands_separator {
+            Some(separator) => group_digits(integer, separator),
+            None => integer.to_string(),
+        };
+        let is_zero = formatted.chars().all(|c| c == '0' || c == '.');
+        let sign = if value < 0.0 && !is_zero { "-" } else { "" };
+
+        match fraction {
+            Some(fraction) => format!("{}{}{}{}", sign, integer, self.decimal_separator, fraction),
+            None => format!("{}{}", sign, integer),
+        }
+    }
+
+    pub fn format_datetime(&self, datetime: &DateTime<Local>) -> String {
+        datetime.format(self.datetime_format).to_string()
+    }
+}
+
+fn group_digits(digits: &str, separator: char) -> String {
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(separator);
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
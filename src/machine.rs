@@ -0,0 +1,72 @@
+//! Where a session took place, for people who work on several machines.
+
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Machine {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    pub os: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub editor: Option<String>,
+}
+
+impl Machine {
+    /// Describes the machine ego is running on. Values that can't be found
+    /// are left out.
+    pub fn current() -> Self {
+        Machine {
+            host: hostname(),
+            os: env::consts::OS.to_string(),
+            editor: editor(),
+        }
+    }
+}
+
+fn hostname() -> Option<String> {
+    env::var("HOSTNAME")
+        .or_else(|_| env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .or_else(|| fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .or_else(|| {
+            let output = Command::new("hostname").output().ok()?;
+            output
+                .status
+                .success()
+                .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+        })
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// The editor configured in `$VISUAL` or `$EDITOR`, or else the one whose
+/// integrated terminal ego is running in.
+fn editor() -> Option<String> {
+    let configured = ["VISUAL", "EDITOR"].into_iter().find_map(|var| {
+        let command = env::var(var).ok()?;
+        let program = command.split_whitespace().next()?;
+        Path::new(program)
+            .file_stem()
+            .map(|name| name.to_string_lossy().into_owned())
+    });
+    configured.or_else(|| {
+        [
+            ("NVIM", "nvim"),
+            ("VIM_TERMINAL", "vim"),
+            ("INSIDE_EMACS", "emacs"),
+            ("ZED_TERM", "zed"),
+        ]
+        .into_iter()
+        .find(|(var, _)| env::var_os(var).is_some())
+        .map(|(_, editor)| editor.to_string())
+        .or_else(|| match env::var("TERM_PROGRAM").ok()?.as_str() {
+            "vscode" => Some("vscode".to_string()),
+            _ => None,
+        })
+    })
+}
@@ -1,16 +1,163 @@
+mod config;
+mod error;
+mod export;
+mod git;
+mod gitignore;
+mod history;
+mod hooks;
+mod idle;
+mod info;
+mod locale;
+mod machine;
+mod output;
+mod plugin;
+mod report;
+mod scan;
 mod session;
+mod sqlite;
+mod stats;
+mod terms;
+#[cfg(test)]
+mod test_util;
+mod tokei;
+mod tracker;
 mod ui;
 
-use anyhow::Result;
-use chrono::Local;
-use clap::{Parser, Subcommand};
-use session::Session;
+use anyhow::{bail, Context, Result};
+use chrono::{Local, NaiveDate, TimeDelta};
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use config::{Config, HistoryBackend};
+use error::EgoError;
+use export::ExportFormat;
+use history::{HistoryEntry, PruneOptions};
+use locale::Locale;
+use machine::Machine;
+use output::{DiffFormat, JsonOutput, ReportFormat, StatusFormat, SummaryFormat};
+use plugin::CommandMetric;
+use report::{Heatmap, Summary};
+use scan::{Metric, ScanDeadline, ScanOptions};
+use session::{EndOptions, FileChange, ModifiedDetection, Session};
+use stats::{GroupBy, HistoryComparison, SessionStats, StatsFormatter, StatusReport};
+use std::collections::BTreeSet;
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::path::{self, Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+use tokei::TokeiCounts;
+use unicode_width::UnicodeWidthStr;
 
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_override_self = true)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    #[arg(long, global = true, value_name = "LOCALE", value_parser = Locale::parse)]
+    locale: Option<Locale>,
+
+    #[arg(long, global = true)]
+    no_emoji: bool,
+
+    #[arg(long, global = true)]
+    json_pretty: bool,
+
+    #[arg(long, global = true)]
+    verbose: bool,
+}
+
+// Start options that decide which files are scanned and how they are
+// counted, layered over `.ego.toml`. Not a doc comment, as clap would use it
+// as the description of every command that flattens these in.
+#[derive(Args)]
+struct ScanArgs {
+    #[arg(long = "generated", value_name = "GLOB")]
+    generated_globs: Vec<String>,
+    #[arg(long = "only", value_name = "GLOB")]
+    only_globs: Vec<String>,
+    #[arg(long = "test", value_name = "GLOB")]
+    test_globs: Vec<String>,
+    #[arg(long, value_name = "SIZE", value_parser = config::parse_size)]
+    max_file_size: Option<u64>,
+    #[arg(long, value_name = "DEPTH")]
+    max_depth: Option<usize>,
+    #[arg(long, conflicts_with = "max_depth")]
+    no_recursive: bool,
+    #[arg(long)]
+    detect_encoding: bool,
+    #[arg(long)]
+    prose_accurate: bool,
+    #[arg(long = "subproject", value_name = "DIR")]
+    subprojects: Vec<PathBuf>,
+    #[arg(long)]
+    detect_subprojects: bool,
+    #[arg(long)]
+    follow_moves: bool,
+    #[arg(long)]
+    analyze_terms: bool,
+    #[arg(long)]
+    count_newline_as_char: bool,
+    #[arg(long)]
+    normalize_trailing_newline: bool,
+    #[arg(long)]
+    no_ignore: bool,
+}
+
+impl ScanArgs {
+    fn apply(&self, options: &mut ScanOptions) {
+        options
+            .generated_globs
+            .extend(self.generated_globs.iter().cloned());
+        options.only_globs.extend(self.only_globs.iter().cloned());
+        options.test_globs.extend(self.test_globs.iter().cloned());
+        if self.max_file_size.is_some() {
+            options.max_file_size = self.max_file_size;
+        }
+        if self.no_recursive {
+            options.max_depth = Some(0);
+        } else if self.max_depth.is_some() {
+            options.max_depth = self.max_depth;
+        }
+        options.detect_encoding |= self.detect_encoding;
+        options.prose_accurate |= self.prose_accurate;
+        options.subprojects.extend(self.subprojects.iter().cloned());
+        options.detect_subprojects |= self.detect_subprojects;
+        options.follow_moves |= self.follow_moves;
+        options.analyze_terms |= self.analyze_terms;
+        options.count_newline_as_char |= self.count_newline_as_char;
+        options.normalize_trailing_newline |= self.normalize_trailing_newline;
+        if self.no_ignore {
+            options.respect_gitignore = false;
+        }
+    }
+
+    /// The `.ego.toml` keys of the settings given on the command line.
+    fn given(&self) -> BTreeSet<&'static str> {
+        [
+            ("generated_globs", !self.generated_globs.is_empty()),
+            ("only_globs", !self.only_globs.is_empty()),
+            ("test_globs", !self.test_globs.is_empty()),
+            ("max_file_size", self.max_file_size.is_some()),
+            ("max_depth", self.max_depth.is_some() || self.no_recursive),
+            ("detect_encoding", self.detect_encoding),
+            ("prose_accurate", self.prose_accurate),
+            ("subprojects", !self.subprojects.is_empty()),
+            ("detect_subprojects", self.detect_subprojects),
+            ("follow_moves", self.follow_moves),
+            ("analyze_terms", self.analyze_terms),
+            ("count_newline_as_char", self.count_newline_as_char),
+            (
+                "normalize_trailing_newline",
+                self.normalize_trailing_newline,
+            ),
+            ("respect_gitignore", self.no_ignore),
+        ]
+        .into_iter()
+        .filter_map(|(key, given)| given.then_some(key))
+        .collect()
+    }
 }
 
 #[derive(Subcommand)]
@@ -18,32 +165,1181 @@ enum Commands {
     Start {
         #[arg(value_name = "PROJECT_DIRECTORY")]
         project_directory: String,
+        #[arg(long, value_name = "NAME")]
+        project_name: Option<String>,
+        #[command(flatten)]
+        scan: ScanArgs,
+        #[arg(long, value_name = "COMMAND")]
+        on_start: Option<String>,
+        #[arg(long)]
+        abort_on_hook_failure: bool,
+        #[arg(long, value_name = "COMMAND")]
+        metric_cmd: Option<String>,
+        #[arg(long, value_name = "PATH")]
+        metric_path: Option<String>,
+        #[arg(long)]
+        tokei: bool,
+        #[arg(long, value_name = "DURATION", value_parser = config::parse_timeout)]
+        session_timeout: Option<i64>,
+        #[arg(long)]
+        record_machine: bool,
+        #[arg(long, value_name = "1-5", value_parser = clap::value_parser!(u8).range(1..=5))]
+        mood: Option<u8>,
+        #[arg(long, value_name = "1-5", value_parser = clap::value_parser!(u8).range(1..=5))]
+        energy: Option<u8>,
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(i32).range(1..))]
+        goal_lines: Option<i32>,
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(i32).range(1..))]
+        goal_minutes: Option<i32>,
+        #[arg(long, value_name = "SECONDS")]
+        scan_timeout: Option<u64>,
+        #[arg(long, short)]
+        yes: bool,
+        #[arg(long)]
+        force: bool,
+        #[arg(long, visible_alias = "watch")]
+        track_activity: bool,
+        #[arg(long, requires = "track_activity")]
+        keystrokes: bool,
+        #[arg(long, default_value_t = 300, value_name = "SECONDS")]
+        away_after: u64,
+        #[arg(
+            long,
+            default_value_t = 500,
+            value_name = "MS",
+            requires = "track_activity"
+        )]
+        watch_debounce: u64,
+    },
+    End {
+        #[arg(value_name = "PROJECT_DIRECTORY", default_value = ".")]
+        project_directory: PathBuf,
+        #[arg(long)]
+        keep: bool,
+        #[arg(long, visible_alias = "dry-end", conflicts_with = "keep")]
+        dry_run: bool,
+        #[arg(long, value_enum, default_value = "tui")]
+        format: ReportFormat,
+        #[arg(long, conflicts_with_all = ["format", "summary_only"])]
+        json: bool,
+        #[arg(long, conflicts_with = "format")]
+        summary_only: bool,
+        #[arg(long)]
+        per_file: bool,
+        #[arg(long, visible_alias = "inline")]
+        no_alt_screen: bool,
+        #[arg(long, value_name = "FILE")]
+        append_to: Option<PathBuf>,
+        #[arg(long, value_name = "INCREMENT", value_parser = config::parse_increment)]
+        round_active: Option<i64>,
+        #[arg(long, value_name = "BRANCH")]
+        compare_branch: Option<String>,
+        #[arg(long, value_enum, default_value = "hash")]
+        modified_detection: ModifiedDetection,
+        #[arg(long, default_value_t = 0, value_name = "N")]
+        min_file_lines: i32,
+        #[arg(long, default_value_t = 0.5, value_name = "RATIO", value_parser = config::parse_ratio)]
+        rename_threshold: f64,
+        #[arg(long, value_enum, value_delimiter = ',', value_name = "METRIC")]
+        metrics: Vec<Metric>,
+        #[arg(long, value_name = "LINES")]
+        minor_below: Option<u32>,
+        #[arg(long, value_name = "1-5", value_parser = clap::value_parser!(u8).range(1..=5))]
+        mood: Option<u8>,
+        #[arg(long, value_name = "1-5", value_parser = clap::value_parser!(u8).range(1..=5))]
+        energy: Option<u8>,
+        #[arg(long, value_enum, default_value = "language")]
+        group_by: GroupBy,
+    },
+    Pause {
+        #[arg(value_name = "PROJECT_DIRECTORY", default_value = ".")]
+        project_directory: PathBuf,
+    },
+    Resume {
+        #[arg(value_name = "PROJECT_DIRECTORY", default_value = ".")]
+        project_directory: PathBuf,
+    },
+    Status {
+        #[arg(value_name = "PROJECT_DIRECTORY", default_value = ".")]
+        project_directory: PathBuf,
+        #[arg(long)]
+        rescan: bool,
+        #[arg(long, value_enum, default_value = "text")]
+        format: StatusFormat,
+        #[arg(long, value_name = "DURATION", value_parser = config::parse_timeout)]
+        session_timeout: Option<i64>,
+    },
+    Diff {
+        #[arg(long, value_enum, default_value = "tui")]
+        format: DiffFormat,
+    },
+    Info {
+        #[arg(value_name = "PROJECT_DIRECTORY", default_value = ".")]
+        project_directory: String,
+        #[command(flatten)]
+        scan: ScanArgs,
+    },
+    Uncommitted {
+        #[arg(value_name = "PROJECT_DIRECTORY", default_value = ".")]
+        project_directory: String,
+        #[arg(long, value_enum, default_value = "text")]
+        format: StatusFormat,
+    },
+    WatchStats {
+        #[arg(long, default_value_t = 2, value_name = "SECONDS")]
+        interval: u64,
+    },
+    Render {
+        #[arg(long, value_enum, default_value = "tui")]
+        format: ReportFormat,
+        #[arg(long)]
+        per_file: bool,
+        #[arg(long, visible_alias = "inline")]
+        no_alt_screen: bool,
+        #[arg(long, value_enum, default_value = "language")]
+        group_by: GroupBy,
+    },
+    Open {
+        #[arg(value_name = "ID")]
+        id: Option<String>,
+        #[arg(long, value_enum, default_value = "tui")]
+        format: ReportFormat,
+        #[arg(long)]
+        per_file: bool,
+        #[arg(long, visible_alias = "inline")]
+        no_alt_screen: bool,
+        #[arg(long, value_enum, default_value = "language")]
+        group_by: GroupBy,
+    },
+    Report {
+        #[arg(long, default_value_t = 7, value_name = "DAYS")]
+        days: u32,
+        #[arg(long, conflicts_with_all = ["days", "month", "since"])]
+        week: bool,
+        #[arg(long, conflicts_with_all = ["days", "since"])]
+        month: bool,
+        #[arg(long, value_name = "DATE", conflicts_with = "days")]
+        since: Option<NaiveDate>,
+        #[arg(long, value_name = "HOSTNAME")]
+        host: Option<String>,
+        #[arg(long, value_enum, default_value = "text")]
+        format: SummaryFormat,
+    },
+    History {
+        #[command(subcommand)]
+        command: Option<HistoryCommand>,
+    },
+    Export {
+        #[arg(long, value_enum, default_value = "toggl")]
+        format: ExportFormat,
+        #[arg(value_name = "OUTPUT")]
+        output: PathBuf,
+        #[arg(long, value_name = "INCREMENT", value_parser = config::parse_increment)]
+        round_active: Option<i64>,
     },
-    End,
 }
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+#[derive(Subcommand)]
+enum HistoryCommand {
+    #[command(group(clap::ArgGroup::new("limit").required(true).multiple(true)))]
+    Prune {
+        #[arg(long, value_name = "AGE", value_parser = config::parse_age, group = "limit")]
+        older_than: Option<i64>,
+        #[arg(long, value_name = "N", group = "limit")]
+        keep_last: Option<usize>,
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+/// Starting in a directory with more files than this asks for confirmation
+/// first, as it is most likely the wrong directory.
+const CONFIRM_ABOVE_FILES: usize = 10_000;
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {}", user_message(&err));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Turns an error into a message for the terminal, adding a hint on how to
+/// recover from the failures ego knows about.
+fn user_message(err: &anyhow::Error) -> String {
+    let Some(ego_error) = err.downcast_ref::<EgoError>() else {
+        return format!("{:?}", err);
+    };
+    match ego_error {
+        EgoError::NoSession => format!("{}. Start one with `ego start`.", ego_error),
+        EgoError::SessionActive { .. } => format!(
+            "{}. End it with `ego end`, or pass --force to discard it and start over.",
+            ego_error
+        ),
+        EgoError::CorruptSession { source, .. } => format!(
+            "{} ({}). Remove it to start a new session.",
+            ego_error, source
+        ),
+        EgoError::DirectoryMissing(_) => format!("{}.", ego_error),
+        EgoError::ScanTimeout(_) => format!(
+            "{}. Narrow the scope with --only, --max-depth or --no-recursive, \
+             or raise --scan-timeout.",
+            ego_error
+        ),
+        EgoError::PermissionDenied { .. } => {
+            format!("{}. Check the file's permissions.", ego_error)
+        }
+        EgoError::Io { source, .. } => format!("{}: {}", ego_error, source),
+    }
+}
+
+/// The command line with the `default_flags` configured for its subcommand
+/// inserted right after the subcommand, where flags given explicitly, coming
+/// later, override them.
+fn with_default_flags(mut args: Vec<OsString>) -> Result<Vec<OsString>> {
+    let command = Cli::command();
+    let mut rest = args.iter().enumerate().skip(1);
+    let (index, name) = loop {
+        let Some((index, arg)) = rest.next() else {
+            return Ok(args);
+        };
+        match arg.to_str() {
+            Some("--locale") => {
+                rest.next();
+            }
+            Some(flag) if flag.starts_with('-') => {}
+            Some(name) => match command.find_subcommand(name) {
+                Some(subcommand) => break (index, subcommand.get_name().to_string()),
+                None => return Ok(args),
+            },
+            None => return Ok(args),
+        }
+    };
+
+    if let Some(flags) = Config::load(Path::new("."))?.default_flags.get(&name) {
+        args.splice(index + 1..index + 1, flags.iter().map(OsString::from));
+    }
+    Ok(args)
+}
+
+fn run() -> Result<()> {
+    let cli = Cli::parse_from(with_default_flags(env::args_os().collect())?);
+    let formatter = StatsFormatter::new(cli.locale.unwrap_or_default(), !cli.no_emoji);
+    let json = JsonOutput {
+        pretty: cli.json_pretty,
+    };
 
     match &cli.command {
-        Commands::Start { project_directory } => {
-            let session = Session::new(project_directory)?;
+        Commands::Start {
+            project_directory,
+            project_name,
+            scan,
+            on_start,
+            abort_on_hook_failure,
+            metric_cmd,
+            metric_path,
+            tokei,
+            session_timeout,
+            record_machine,
+            mood,
+            energy,
+            goal_lines,
+            goal_minutes,
+            scan_timeout,
+            yes,
+            force,
+            track_activity,
+            keystrokes,
+            away_after,
+            watch_debounce,
+        } => {
+            let previous_file = Path::new(project_directory).join(scan::SESSION_FILE);
+            if previous_file.is_file() {
+                let previous = Session::load_file(&previous_file)?;
+                if stale_after(*session_timeout, &previous)?
+                    .is_some_and(|timeout| previous.is_stale(Local::now(), timeout))
+                {
+                    end_stale_session(previous, &formatter)?;
+                } else if !*force {
+                    return Err(EgoError::SessionActive {
+                        directory: path::absolute(project_directory)?,
+                        started: previous.start_time,
+                    }
+                    .into());
+                }
+            }
+
+            let config = Config::load(Path::new(project_directory))?;
+            let mut options = config.scan_options();
+            scan.apply(&mut options);
+
+            let deadline = ScanDeadline::after(scan_timeout.map(Duration::from_secs));
+            // Listed before anything expensive runs, so a start in the wrong
+            // directory can be called off cheaply.
+            let survey = Session::survey(project_directory, &options, &deadline)?;
+            if !*yes
+                && io::stdout().is_terminal()
+                && survey.files.len() > CONFIRM_ABOVE_FILES
+                && !confirm(&format!(
+                    "About to track {} files ({} MB). Continue? [y/N] ",
+                    formatter.count(survey.files.len() as i32),
+                    formatter.count(
+                        survey
+                            .files
+                            .values()
+                            .map(|file| file.size)
+                            .sum::<u64>()
+                            .div_ceil(1_000_000) as i32
+                    )
+                ))?
+            {
+                println!("No session started.");
+                return Ok(());
+            }
+
+            // Measured before the session is created, so the command's run
+            // time isn't counted as part of the session either.
+            let command_metric = metric_cmd
+                .as_ref()
+                .or(config.metric_cmd.as_ref())
+                .map(|command| {
+                    CommandMetric::start(
+                        command,
+                        metric_path.as_deref().or(config.metric_path.as_deref()),
+                        Path::new(project_directory),
+                    )
+                })
+                .transpose()?;
+            let tokei_counts = (*tokei || config.tokei)
+                .then(|| TokeiCounts::start(Path::new(project_directory)))
+                .transpose()?;
+            let mut session = Session::new(
+                project_directory,
+                project_name.clone(),
+                options,
+                survey,
+                &deadline,
+            )?;
+            session.command_metric = command_metric;
+            session.tokei = tokei_counts;
+            if let Some(threshold) = config.idle_threshold {
+                session.idle_threshold_secs = threshold.0;
+            }
+            if *record_machine || config.record_machine {
+                session.machine = Some(Machine::current());
+            }
+            session.mood = *mood;
+            session.energy = *energy;
+            session.goal_lines = *goal_lines;
+            session.goal_minutes = *goal_minutes;
             session.save()?;
-            println!("Session started in directory: {}", project_directory);
-            println!("Initial line count: {}", session.initial_line_count);
+            println!(
+                "Session started for {} in directory: {}",
+                session.project_name,
+                formatter.path(&session.project_directory)
+            );
+            println!("Session ID: {}", session.id);
+            println!("Started at: {}", formatter.datetime(&session.start_time));
+            println!(
+                "Initial line count: {}",
+                formatter.count(session.initial_line_count)
+            );
+            if cli.verbose {
+                print_encoding_breakdown(&session, &formatter);
+            }
+
+            if let Some(command) = on_start.as_ref().or(config.on_start.as_ref()) {
+                if let Err(err) = hooks::run(command, "start", &session) {
+                    if *abort_on_hook_failure || config.abort_on_hook_failure {
+                        session.discard()?;
+                        return Err(err.context("session discarded"));
+                    }
+                    eprintln!("Warning: {:#}", err);
+                }
+            }
+
+            if *track_activity {
+                if *keystrokes {
+                    println!("Tracking keystrokes in this terminal. Press Ctrl+C to stop.");
+                    tracker::track_keystrokes(&mut session)?;
+                } else {
+                    println!("Watching for file changes. Press Ctrl+C to stop.");
+                    tracker::track_file_changes(
+                        &mut session,
+                        Duration::from_secs(*away_after),
+                        Duration::from_millis(*watch_debounce),
+                    )?;
+                }
+            }
         }
-        Commands::End => {
-            if let Some(mut session) = Session::load()? {
+        Commands::End {
+            project_directory,
+            keep,
+            dry_run,
+            format,
+            json: json_flag,
+            summary_only,
+            per_file,
+            no_alt_screen,
+            append_to,
+            round_active,
+            compare_branch,
+            modified_detection,
+            min_file_lines,
+            rename_threshold,
+            metrics,
+            minor_below,
+            mood,
+            energy,
+            group_by,
+        } => {
+            let append_to = append_to.as_deref().map(path::absolute).transpose()?;
+            if let Some(mut session) = Session::load(project_directory)? {
+                if let Some(warning) = session.directory_mismatch() {
+                    eprintln!("Warning: {}", warning);
+                }
+                session.mood = mood.or(session.mood);
+                session.energy = energy.or(session.energy);
                 let end_time = Local::now();
+                session.end_time = Some(end_time);
+                let branch_comparison = compare_branch
+                    .as_deref()
+                    .map(|branch| {
+                        git::compare_branch(&session.project_directory, &session.options, branch)
+                    })
+                    .transpose()?;
+
+                if let Some(metric) = &mut session.command_metric {
+                    if let Err(err) = metric.finish(&session.project_directory) {
+                        eprintln!("Warning: {:#}", err);
+                    }
+                }
+                if let Some(counts) = &mut session.tokei {
+                    if let Err(err) = counts.finish(&session.project_directory) {
+                        eprintln!("Warning: {:#}", err);
+                    }
+                }
+                session.end(&EndOptions {
+                    keep: *keep,
+                    dry_run: *dry_run,
+                    modified_detection: *modified_detection,
+                    min_file_lines: *min_file_lines,
+                    rename_threshold: *rename_threshold,
+                })?;
+                let config = Config::load(&session.project_directory)?;
+                let average_since = end_time - TimeDelta::days(history::AVERAGE_WINDOW_DAYS);
+                let history_average = history::average_lines_per_active_hour(
+                    &history::load_since(Some(average_since))?,
+                    average_since,
+                );
 
-                session.end()?;
+                let mut stats = SessionStats::new(&session, end_time);
+                stats.history_comparison = history_average.map(|average| HistoryComparison {
+                    days: history::AVERAGE_WINDOW_DAYS,
+                    average_lines_per_active_hour: average,
+                });
+                if let Some(increment) = round_active {
+                    stats.round_active(*increment);
+                }
+                stats.branch_comparison = branch_comparison;
+                if !metrics.is_empty() {
+                    stats.metrics_shown = metrics.clone();
+                }
+                stats.minor_change_lines = minor_below.or(config.minor_change_lines);
+                stats.group_by = *group_by;
+                if !*dry_run {
+                    if config.skip_minor_sessions && stats.is_minor_change() {
+                        eprintln!("Only minor changes; the session was not added to the history.");
+                    } else {
+                        history::append(&HistoryEntry::new(&session, end_time))?;
+                        history::save_report(&stats)?;
+                    }
+                }
+                if let Some(path) = append_to.as_ref().filter(|_| !*dry_run) {
+                    export::append_summary(path, &stats, &formatter)?;
+                }
 
-                ui::draw_stats(&session, end_time)?;
+                if *summary_only {
+                    println!("{}", formatter.one_line(&stats));
+                    return Ok(());
+                }
+
+                print_stats(
+                    &stats,
+                    if *json_flag {
+                        ReportFormat::Json
+                    } else {
+                        *format
+                    },
+                    &formatter,
+                    &json,
+                    *per_file,
+                    *no_alt_screen,
+                )?;
+            } else {
+                println!("No active session found.");
+            }
+        }
+        Commands::Status {
+            project_directory,
+            rescan,
+            format,
+            session_timeout,
+        } => {
+            if let Some(session) = Session::load(project_directory)? {
+                if stale_after(*session_timeout, &session)?
+                    .is_some_and(|timeout| session.is_stale(Local::now(), timeout))
+                {
+                    let last_seen = formatter.datetime(&session.last_seen());
+                    if *format == StatusFormat::Text
+                        && !json.pretty
+                        && io::stdin().is_terminal()
+                        && confirm(&format!(
+                            "Session {} has not been used since {}. End it as of then? [y/N] ",
+                            session.id, last_seen
+                        ))?
+                    {
+                        return end_stale_session(session, &formatter);
+                    }
+                    eprintln!(
+                        "Warning: session {} has not been used since {}",
+                        session.id, last_seen
+                    );
+                }
+                let (current, changes) = session.pending(*rescan)?;
+                let mut report = StatusReport::new(&session, &current, Local::now());
+                report.file_changes = changes;
+
+                if *format == StatusFormat::Json || json.pretty {
+                    json.print(&report)?;
+                } else {
+                    println!(
+                        "Session {} in directory: {}",
+                        if report.paused { "paused" } else { "active" },
+                        formatter.path(&report.project_directory)
+                    );
+                    println!("Session ID: {}", report.id);
+                    println!("Elapsed time: {}", formatter.duration(report.elapsed_secs));
+                    println!("Active time: {}", formatter.duration(report.active_secs));
+                    println!("Idle time: {}", formatter.duration(report.idle_secs));
+                    if report.paused_secs > 0 {
+                        println!("Paused time: {}", formatter.duration(report.paused_secs));
+                    }
+                    println!(
+                        "Current line count: {}",
+                        formatter.count(report.current_line_count)
+                    );
+                    println!(
+                        "Lines written so far: {}",
+                        formatter.signed_count(report.lines_written)
+                    );
+                    println!(
+                        "Characters written so far: {}",
+                        formatter.signed_count(report.chars_written)
+                    );
+                    if !report.file_changes.is_empty() {
+                        println!();
+                        print_diff_stat(&session, &report.file_changes, &formatter);
+                    }
+                    if cli.verbose {
+                        print_encoding_breakdown(&session, &formatter);
+                    }
+                }
+            } else {
+                println!("No active session found.");
+            }
+        }
+        Commands::Pause { project_directory } => {
+            if let Some(mut session) = Session::load(project_directory)? {
+                let now = Local::now();
+                if session.pause(now) {
+                    session.save()?;
+                    println!(
+                        "Session paused at {}. Run `ego resume` to continue.",
+                        formatter.datetime(&now)
+                    );
+                } else {
+                    println!("The session is already paused.");
+                }
             } else {
                 println!("No active session found.");
             }
         }
+        Commands::Resume { project_directory } => {
+            if let Some(mut session) = Session::load(project_directory)? {
+                if let Some(paused_secs) = session.resume(Local::now()) {
+                    session.save()?;
+                    println!(
+                        "Session resumed after a pause of {}.",
+                        formatter.duration(paused_secs)
+                    );
+                } else {
+                    println!("The session isn't paused.");
+                }
+            } else {
+                println!("No active session found.");
+            }
+        }
+        Commands::Diff { format } => {
+            if let Some(session) = Session::load(Path::new("."))? {
+                let (_, changes) = session.pending(false)?;
+                match format {
+                    DiffFormat::Tui => ui::draw_diff(&session, changes, &formatter)?,
+                    DiffFormat::Text => print_diff_stat(&session, &changes, &formatter),
+                }
+            } else {
+                println!("No active session found.");
+            }
+        }
+        Commands::WatchStats { interval } => {
+            if let Some(file) = Session::find(Path::new(".")) {
+                ui::watch_stats(&file, &formatter, Duration::from_secs((*interval).max(1)))?;
+                if !file.exists() {
+                    println!("Session ended.");
+                }
+            } else {
+                println!("No active session found.");
+            }
+        }
+        Commands::Info {
+            project_directory,
+            scan,
+        } => {
+            let directory = Path::new(project_directory);
+            let config = Config::load(directory)?;
+            let mut options = config.scan_options();
+            scan.apply(&mut options);
+            let config_keys = Config::keys(directory)?;
+            let global_keys = Config::global_keys()?;
+            let flags = scan.given();
+
+            println!(
+                "Project directory: {}",
+                formatter.path(&fs::canonicalize(directory)?)
+            );
+            for (label, config_file) in [
+                ("Global config", config::global_config_file()?),
+                ("Config file", Config::file(directory)),
+            ] {
+                println!(
+                    "{}: {} ({})",
+                    label,
+                    formatter.path(&config_file),
+                    if config_file.exists() {
+                        "found"
+                    } else {
+                        "not found"
+                    }
+                );
+            }
+            println!(
+                "Session file: {}",
+                formatter.path(&directory.join(scan::SESSION_FILE))
+            );
+            let (backend, store) = history::store_location()?;
+            println!(
+                "History: {} ({})",
+                formatter.path(&store),
+                match backend {
+                    HistoryBackend::Jsonl => "jsonl",
+                    HistoryBackend::Sqlite => "sqlite",
+                }
+            );
+            println!();
+
+            let sources = info::Sources {
+                global_keys: &global_keys,
+                config_keys: &config_keys,
+                flags: &flags,
+            };
+            let idle_threshold = config
+                .idle_threshold
+                .map_or(session::IDLE_THRESHOLD_SECS, |threshold| threshold.0);
+            let settings = info::settings(&options, idle_threshold, &sources);
+            let width = settings
+                .iter()
+                .map(|setting| setting.name.len())
+                .max()
+                .unwrap_or(0);
+            for setting in settings {
+                println!(
+                    "{:<width$}  {}  [{}]",
+                    setting.name,
+                    setting.value,
+                    setting.source,
+                    width = width
+                );
+            }
+        }
+        Commands::Uncommitted {
+            project_directory,
+            format,
+        } => {
+            let directory = Path::new(project_directory);
+            let options = Config::load(directory)?.scan_options();
+            let comparison = git::compare_branch(directory, &options, "HEAD")?;
+
+            if *format == StatusFormat::Json || json.pretty {
+                json.print(&comparison)?;
+            } else {
+                println!(
+                    "Uncommitted changes in {}:",
+                    formatter.path(&fs::canonicalize(directory)?)
+                );
+                println!(
+                    "Lines: +{} / -{}",
+                    formatter.count(comparison.lines_added as i32),
+                    formatter.count(comparison.lines_removed as i32)
+                );
+                println!(
+                    "Files: {} added, {} changed, {} removed",
+                    formatter.count(comparison.files_added as i32),
+                    formatter.count(comparison.files_changed as i32),
+                    formatter.count(comparison.files_removed as i32)
+                );
+            }
+        }
+        Commands::Render {
+            format,
+            per_file,
+            no_alt_screen,
+            group_by,
+        } => {
+            let session: Session = serde_json::from_reader(io::stdin().lock())
+                .context("invalid session JSON on stdin")?;
+            let Some(end_time) = session
+                .end_time
+                .filter(|_| session.final_line_count.is_some())
+            else {
+                bail!(
+                    "the session on stdin has not been ended; pass a finalized session such as \
+                     .ego_session.done.json from `ego end --keep`"
+                );
+            };
+            let mut stats = SessionStats::new(&session, end_time);
+            stats.group_by = *group_by;
+            print_stats(
+                &stats,
+                *format,
+                &formatter,
+                &json,
+                *per_file,
+                *no_alt_screen,
+            )?;
+        }
+        Commands::Open {
+            id,
+            format,
+            per_file,
+            no_alt_screen,
+            group_by,
+        } => {
+            let id = match id {
+                Some(id) => id.clone(),
+                None => match history::load()?
+                    .into_iter()
+                    .max_by_key(|entry| entry.end_time)
+                {
+                    Some(entry) => entry.id,
+                    None => {
+                        println!("No sessions in the history.");
+                        return Ok(());
+                    }
+                },
+            };
+            let Some(mut stats) = history::load_report(&id)? else {
+                bail!(
+                    "no saved report for session {}; only sessions ended with this version of ego can be opened",
+                    id
+                );
+            };
+            stats.group_by = *group_by;
+            print_stats(
+                &stats,
+                *format,
+                &formatter,
+                &json,
+                *per_file,
+                *no_alt_screen,
+            )?;
+        }
+        Commands::Report {
+            days,
+            week,
+            month,
+            since,
+            host,
+            format,
+        } => {
+            let (first_day, last_day) = if *week {
+                report::week()
+            } else if *month {
+                report::month()
+            } else if let Some(since) = since {
+                (*since, Local::now().date_naive())
+            } else {
+                report::window(*days)
+            };
+            let since = first_day
+                .and_hms_opt(0, 0, 0)
+                .and_then(|midnight| midnight.and_local_timezone(Local).earliest());
+            let entries: Vec<HistoryEntry> = history::load_since(since)?
+                .into_iter()
+                .filter(|entry| {
+                    let day = entry.start_time.date_naive();
+                    day >= first_day && day <= last_day
+                })
+                .filter(|entry| {
+                    host.as_ref().is_none_or(|host| {
+                        entry
+                            .machine
+                            .as_ref()
+                            .and_then(|machine| machine.host.as_ref())
+                            .is_some_and(|recorded| recorded.eq_ignore_ascii_case(host))
+                    })
+                })
+                .collect();
+
+            let summary = Summary::new(&entries, first_day, last_day);
+            let heading = format!(
+                "Sessions{} from {} to {}: {}",
+                host.as_ref()
+                    .map(|host| format!(" on {}", host))
+                    .unwrap_or_default(),
+                first_day,
+                last_day,
+                entries.len()
+            );
+            let totals = vec![
+                format!(
+                    "Active time: {}",
+                    formatter.duration(summary.total.active_secs)
+                ),
+                format!(
+                    "Lines written: {}",
+                    formatter.signed_count(summary.total.lines_written)
+                ),
+                format!(
+                    "Per session: {} active, {} lines",
+                    formatter.short_duration(summary.total.average_active_secs()),
+                    formatter.rate(summary.total.average_lines())
+                ),
+            ];
+            let heatmap = Heatmap::new(&entries, first_day, last_day);
+            if *format == SummaryFormat::Tui {
+                return ui::draw_summary(
+                    &heading,
+                    &totals,
+                    &summary,
+                    &heatmap.render(false),
+                    &formatter,
+                );
+            }
+
+            println!("{}", heading);
+            for line in &totals {
+                println!("{}", line);
+            }
+            if !summary.projects.is_empty() {
+                println!();
+                print_table(
+                    &report::PROJECT_HEADER,
+                    &summary.project_rows(&formatter),
+                    1,
+                );
+            }
+            println!();
+            print_table(&report::DAY_HEADER, &summary.day_rows(&formatter), 1);
+            println!();
+            for line in heatmap.render(io::stdout().is_terminal()) {
+                println!("{}", line);
+            }
+
+            for (label, pace) in [
+                ("mood", report::pace_by_rating(&entries, |entry| entry.mood)),
+                (
+                    "energy",
+                    report::pace_by_rating(&entries, |entry| entry.energy),
+                ),
+            ] {
+                if pace.iter().all(Option::is_none) {
+                    continue;
+                }
+                println!();
+                println!("Lines per active hour by {}:", label);
+                for (index, pace) in pace.iter().enumerate() {
+                    match pace {
+                        Some((rate, sessions)) => println!(
+                            "  {}/5  {} ({} sessions)",
+                            index + 1,
+                            formatter.rate(*rate),
+                            formatter.count(*sessions as i32)
+                        ),
+                        None => println!("  {}/5  -", index + 1),
+                    }
+                }
+            }
+        }
+        Commands::History { command } => match command {
+            None => print_history(&history::load()?, &formatter),
+            Some(HistoryCommand::Prune {
+                older_than,
+                keep_last,
+                dry_run,
+            }) => {
+                let options = PruneOptions {
+                    older_than: older_than.map(TimeDelta::days),
+                    keep_last: *keep_last,
+                    dry_run: *dry_run,
+                };
+                let removed = history::prune(&options, Local::now())?;
+                for entry in &removed {
+                    println!(
+                        "{} {} {} ({} lines)",
+                        entry.id,
+                        formatter.datetime(&entry.start_time),
+                        entry.project_name,
+                        formatter.signed_count(entry.lines_written)
+                    );
+                }
+                println!(
+                    "{} {} sessions",
+                    if *dry_run { "Would remove" } else { "Removed" },
+                    formatter.count(removed.len() as i32)
+                );
+            }
+        },
+        Commands::Export {
+            format,
+            output,
+            round_active,
+        } => {
+            let entries = history::load()?;
+            let mut writer = BufWriter::new(File::create(output)?);
+            export::export(&entries, *format, *round_active, &mut writer)?;
+            println!(
+                "Exported {} sessions to {}",
+                entries.len(),
+                formatter.path(output)
+            );
+        }
     }
 
     Ok(())
 }
+
+fn print_stats(
+    stats: &SessionStats,
+    format: ReportFormat,
+    formatter: &StatsFormatter,
+    json: &JsonOutput,
+    per_file: bool,
+    inline: bool,
+) -> Result<()> {
+    let format = if json.pretty {
+        ReportFormat::Json
+    } else {
+        format
+    };
+    match format {
+        ReportFormat::Json => json.print(stats)?,
+        ReportFormat::Csv => export::write_file_changes_csv(stats, &mut io::stdout().lock())?,
+        ReportFormat::Markdown => {
+            export::write_markdown(stats, formatter, &mut io::stdout().lock())?
+        }
+        ReportFormat::Tui => ui::draw_stats(stats, formatter, per_file, inline)?,
+    }
+    Ok(())
+}
+
+/// The `--session-timeout`, or else the one set in the session's `.ego.toml`.
+fn stale_after(timeout: Option<i64>, session: &Session) -> Result<Option<i64>> {
+    match timeout {
+        Some(timeout) => Ok(Some(timeout)),
+        None => Ok(Config::load(&session.project_directory)?
+            .session_timeout
+            .map(|timeout| timeout.0)),
+    }
+}
+
+/// Ends a session that was left running, as of the last time it was in use
+/// rather than now, and adds it to the history. The final line counts are
+/// still taken from the files as they are now.
+fn end_stale_session(mut session: Session, formatter: &StatsFormatter) -> Result<()> {
+    let end_time = session.last_seen();
+    session.end_time = Some(end_time);
+    session.end(&EndOptions {
+        keep: false,
+        dry_run: false,
+        modified_detection: ModifiedDetection::Hash,
+        min_file_lines: 0,
+        rename_threshold: 0.5,
+    })?;
+
+    let config = Config::load(&session.project_directory)?;
+    let mut stats = SessionStats::new(&session, end_time);
+    stats.minor_change_lines = config.minor_change_lines;
+    if !(config.skip_minor_sessions && stats.is_minor_change()) {
+        history::append(&HistoryEntry::new(&session, end_time))?;
+        history::save_report(&stats)?;
+    }
+    println!(
+        "Ended abandoned session {} as of {}: {}",
+        session.id,
+        formatter.datetime(&end_time),
+        formatter.summary(&stats)
+    );
+    Ok(())
+}
+
+/// Prints rows under a header with each column padded to its widest cell.
+/// The first `text_columns` columns are left-aligned, the numbers after them
+/// right-aligned.
+fn print_table(header: &[&str], rows: &[Vec<String>], text_columns: usize) {
+    let widths: Vec<usize> = (0..header.len())
+        .map(|column| {
+            rows.iter()
+                .map(|row| row[column].width())
+                .chain([header[column].len()])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let line = |cells: Vec<&str>| {
+        cells
+            .iter()
+            .enumerate()
+            .map(|(column, cell)| {
+                let padding = " ".repeat(widths[column] - cell.width());
+                if column < text_columns {
+                    format!("{}{}", cell, padding)
+                } else {
+                    format!("{}{}", padding, cell)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("  ")
+    };
+
+    println!("{}", line(header.to_vec()));
+    for row in rows {
+        println!("{}", line(row.iter().map(String::as_str).collect()));
+    }
+}
+
+/// Lists past sessions, oldest first, with totals across all of them.
+fn print_history(entries: &[HistoryEntry], formatter: &StatsFormatter) {
+    if entries.is_empty() {
+        println!("No sessions in the history.");
+        return;
+    }
+
+    let header = [
+        "Started", "Project", "Duration", "Active", "Lines", "Chars", "Files",
+    ];
+    let rows: Vec<Vec<String>> = entries
+        .iter()
+        .map(|entry| {
+            vec![
+                formatter.datetime(&entry.start_time),
+                entry.project_name.clone(),
+                formatter.short_duration(entry.duration_secs()),
+                formatter.short_duration(entry.active_time_seconds),
+                formatter.signed_count(entry.lines_written),
+                formatter.signed_count(entry.chars_written),
+                formatter.count(entry.files_changed as i32),
+            ]
+        })
+        .collect();
+    print_table(&header, &rows, 2);
+
+    let active_secs: i64 = entries.iter().map(|entry| entry.active_time_seconds).sum();
+    let lines_written: i32 = entries.iter().map(|entry| entry.lines_written).sum();
+    println!();
+    println!(
+        "Total: {} sessions, {} active hours, {} lines",
+        formatter.count(entries.len() as i32),
+        formatter.rate(active_secs as f64 / 3600.0),
+        formatter.signed_count(lines_written)
+    );
+}
+
+/// Asks a yes/no question on the terminal, defaulting to no.
+fn confirm(prompt: &str) -> Result<bool> {
+    print!("{}", prompt);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+fn print_encoding_breakdown(session: &Session, formatter: &StatsFormatter) {
+    let breakdown = session.encoding_breakdown();
+    if breakdown.is_empty() {
+        println!("Encodings: not detected (start with --detect-encoding)");
+        return;
+    }
+    println!("Encodings:");
+    for (encoding, files) in breakdown {
+        println!("  {}: {} files", encoding, formatter.count(files as i32));
+    }
+}
+
+/// Prints changes like `git diff --stat`, with each file's changed lines and
+/// a bar of additions and removals scaled to the largest change.
+fn print_diff_stat(session: &Session, changes: &[FileChange], formatter: &StatsFormatter) {
+    const BAR_WIDTH: i32 = 40;
+
+    let paths: Vec<String> = changes
+        .iter()
+        .map(|change| {
+            let relative = change
+                .path
+                .strip_prefix(&session.project_directory)
+                .unwrap_or(&change.path);
+            formatter.path(relative)
+        })
+        .collect();
+    let path_width = paths
+        .iter()
+        .map(|path| path.chars().count())
+        .max()
+        .unwrap_or(0);
+    let largest = changes
+        .iter()
+        .map(|change| {
+            let (added, removed) = change.added_removed();
+            added + removed
+        })
+        .max()
+        .unwrap_or(0);
+    let scale = |lines: i32| {
+        if largest > BAR_WIDTH {
+            (lines * BAR_WIDTH + largest - 1) / largest
+        } else {
+            lines
+        }
+    };
+
+    for (change, path) in changes.iter().zip(&paths) {
+        let (added, removed) = change.added_removed();
+        println!(
+            " {} {:<path_width$} | {:>6} {}{}",
+            formatter.change_icon(change.kind),
+            path,
+            formatter.count(added + removed),
+            "+".repeat(scale(added) as usize),
+            "-".repeat(scale(removed) as usize)
+        );
+    }
+
+    let (insertions, deletions) = stats::line_totals(changes);
+    println!(
+        " {} files changed, {} insertions(+), {} deletions(-)",
+        formatter.count(changes.len() as i32),
+        formatter.count(insertions),
+        formatter.count(deletions)
+    );
+}
@@ -0,0 +1,47 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    Tui,
+    Json,
+    Csv,
+    Markdown,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DiffFormat {
+    Tui,
+    Text,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SummaryFormat {
+    Text,
+    Tui,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StatusFormat {
+    Text,
+    Json,
+}
+
+/// Decides how JSON is written for every command that emits it.
+#[derive(Clone, Copy)]
+pub struct JsonOutput {
+    pub pretty: bool,
+}
+
+impl JsonOutput {
+    pub fn print<T: Serialize>(&self, value: &T) -> Result<()> {
+        let json = if self.pretty {
+            serde_json::to_string_pretty(value)?
+        } else {
+            serde_json::to_string(value)?
+        };
+        println!("{}", json);
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+use crate::hooks;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Counts from an external tool such as `cloc --json`, `tokei --output json`
+/// or `scc --format json`, run over the project at the start and end of a
+/// session.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CommandMetric {
+    pub command: String,
+    /// Dot-separated path to the part of the JSON output to read, such as
+    /// `SUM.code`. Without one, every number in the output is read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    pub initial: BTreeMap<String, f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last: Option<BTreeMap<String, f64>>,
+}
+
+impl CommandMetric {
+    pub fn start(command: &str, path: Option<&str>, dir: &Path) -> Result<Self> {
+        Ok(CommandMetric {
+            command: command.to_string(),
+            path: path.map(str::to_string),
+            initial: measure(command, path, dir)?,
+            last: None,
+        })
+    }
+
+    pub fn finish(&mut self, dir: &Path) -> Result<()> {
+        self.last = Some(measure(&self.command, self.path.as_deref(), dir)?);
+        Ok(())
+    }
+
+    /// The values read at both the start and the end, as `(name, initial,
+    /// last)`.
+    pub fn changes(&self) -> Vec<(&str, f64, f64)> {
+        let Some(last) = &self.last else {
+            return Vec::new();
+        };
+        self.initial
+            .iter()
+            .filter_map(|(name, initial)| {
+                last.get(name).map(|last| (name.as_str(), *initial, *last))
+            })
+            .collect()
+    }
+}
+
+/// Runs `command` in `dir` and reads the numbers at `path` in the JSON it
+/// prints, keyed by their dot-separated path.
+fn measure(command: &str, path: Option<&str>, dir: &Path) -> Result<BTreeMap<String, f64>> {
+    let output = hooks::shell(command)
+        .current_dir(dir)
+        .output()
+        .with_context(|| format!("failed to run metric command `{}`", command))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!(
+            "metric command `{}` failed with {}{}{}",
+            command,
+            output.status,
+            if stderr.trim().is_empty() { "" } else { ": " },
+            stderr.trim()
+        );
+    }
+
+    let json: Value = serde_json::from_slice(&output.stdout)
+        .with_context(|| format!("metric command `{}` did not print JSON", command))?;
+    let mut selected = &json;
+    for key in path.into_iter().flat_map(|path| path.split('.')) {
+        selected = match selected {
+            Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => selected.get(key),
+        }
+        .ok_or_else(|| {
+            anyhow!(
+                "`{}` not found in the output of metric command `{}`",
+                path.unwrap_or_default(),
+                command
+            )
+        })?;
+    }
+
+    let mut values = BTreeMap::new();
+    collect_numbers(selected, path.unwrap_or_default(), &mut values);
+    if values.is_empty() {
+        bail!("no numbers in the output of metric command `{}`", command);
+    }
+    Ok(values)
+}
+
+fn collect_numbers(value: &Value, name: &str, values: &mut BTreeMap<String, f64>) {
+    let child = |key: &str| {
+        if name.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", name, key)
+        }
+    };
+    match value {
+        Value::Number(number) => {
+            if let Some(number) = number.as_f64() {
+                let name = if name.is_empty() { "value" } else { name };
+                values.insert(name.to_string(), number);
+            }
+        }
+        Value::Object(fields) => {
+            for (key, field) in fields {
+                collect_numbers(field, &child(key), values);
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                collect_numbers(item, &child(&i.to_string()), values);
+            }
+        }
+        _ => {}
+    }
+}
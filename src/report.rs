@@ -0,0 +1,245 @@
+use crate::history::HistoryEntry;
+use crate::stats::StatsFormatter;
+use chrono::{Datelike, Duration, Local, NaiveDate, Timelike};
+use crossterm::style::{Color, Stylize};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+const HEAT_LEVELS: [char; 5] = ['·', '░', '▒', '▓', '█'];
+const HEAT_COLORS: [Color; 5] = [
+    Color::DarkGrey,
+    Color::DarkGreen,
+    Color::DarkGreen,
+    Color::Green,
+    Color::Green,
+];
+
+pub const PROJECT_HEADER: [&str; 7] = [
+    "Project",
+    "Sessions",
+    "Active",
+    "Avg active",
+    "Lines",
+    "Avg lines",
+    "Lines/active h",
+];
+pub const DAY_HEADER: [&str; 5] = ["Day", "Sessions", "Duration", "Active", "Lines"];
+
+/// Sessions, time and lines written, added up over a group of sessions such
+/// as one project's or one day's.
+#[derive(Clone, Copy, Default)]
+pub struct Totals {
+    pub sessions: usize,
+    pub duration_secs: i64,
+    pub active_secs: i64,
+    pub lines_written: i32,
+}
+
+impl Totals {
+    fn add(&mut self, entry: &HistoryEntry) {
+        self.sessions += 1;
+        self.duration_secs += entry.duration_secs();
+        self.active_secs += entry.active_time_seconds;
+        self.lines_written += entry.lines_written;
+    }
+
+    pub fn average_active_secs(&self) -> i64 {
+        match self.sessions {
+            0 => 0,
+            sessions => self.active_secs / sessions as i64,
+        }
+    }
+
+    pub fn average_lines(&self) -> f64 {
+        match self.sessions {
+            0 => 0.0,
+            sessions => f64::from(self.lines_written) / sessions as f64,
+        }
+    }
+
+    pub fn lines_per_active_hour(&self) -> f64 {
+        if self.active_secs <= 0 {
+            return 0.0;
+        }
+        f64::from(self.lines_written) * 3600.0 / self.active_secs as f64
+    }
+}
+
+/// The history of a range of days added up overall, per project and per day.
+pub struct Summary {
+    pub total: Totals,
+    /// Most active time first.
+    pub projects: Vec<(String, Totals)>,
+    /// Every day of the range, including days without sessions.
+    pub days: Vec<(NaiveDate, Totals)>,
+}
+
+impl Summary {
+    pub fn new(entries: &[HistoryEntry], first_day: NaiveDate, last_day: NaiveDate) -> Self {
+        let mut total = Totals::default();
+        let mut projects: BTreeMap<String, Totals> = BTreeMap::new();
+        let mut days = BTreeMap::new();
+        let mut day = first_day;
+        while day <= last_day {
+            days.insert(day, Totals::default());
+            day += Duration::days(1);
+        }
+
+        for entry in entries {
+            total.add(entry);
+            projects
+                .entry(entry.project_name.clone())
+                .or_default()
+                .add(entry);
+            if let Some(day) = days.get_mut(&entry.start_time.date_naive()) {
+                day.add(entry);
+            }
+        }
+
+        let mut projects: Vec<(String, Totals)> = projects.into_iter().collect();
+        projects.sort_by_key(|(_, totals)| Reverse(totals.active_secs));
+        Summary {
+            total,
+            projects,
+            days: days.into_iter().collect(),
+        }
+    }
+
+    /// Rows matching `PROJECT_HEADER`.
+    pub fn project_rows(&self, formatter: &StatsFormatter) -> Vec<Vec<String>> {
+        self.projects
+            .iter()
+            .map(|(project, totals)| {
+                vec![
+                    project.clone(),
+                    formatter.count(totals.sessions as i32),
+                    formatter.short_duration(totals.active_secs),
+                    formatter.short_duration(totals.average_active_secs()),
+                    formatter.signed_count(totals.lines_written),
+                    formatter.rate(totals.average_lines()),
+                    formatter.rate(totals.lines_per_active_hour()),
+                ]
+            })
+            .collect()
+    }
+
+    /// Rows matching `DAY_HEADER`.
+    pub fn day_rows(&self, formatter: &StatsFormatter) -> Vec<Vec<String>> {
+        self.days
+            .iter()
+            .map(|(day, totals)| {
+                vec![
+                    day.format("%a %Y-%m-%d").to_string(),
+                    formatter.count(totals.sessions as i32),
+                    formatter.short_duration(totals.duration_secs),
+                    formatter.short_duration(totals.active_secs),
+                    formatter.signed_count(totals.lines_written),
+                ]
+            })
+            .collect()
+    }
+}
+
+/// Active minutes per hour of day, for each day in the window.
+pub struct Heatmap {
+    pub days: BTreeMap<NaiveDate, [u32; 24]>,
+}
+
+impl Heatmap {
+    pub fn new(entries: &[HistoryEntry], first_day: NaiveDate, last_day: NaiveDate) -> Self {
+        let mut days = BTreeMap::new();
+        let mut day = first_day;
+        while day <= last_day {
+            days.insert(day, [0; 24]);
+            day += Duration::days(1);
+        }
+
+        for sample in entries.iter().flat_map(|entry| &entry.activity_samples) {
+            if let Some(hours) = days.get_mut(&sample.date_naive()) {
+                hours[sample.hour() as usize] += 1;
+            }
+        }
+
+        Heatmap { days }
+    }
+
+    fn max_bucket(&self) -> u32 {
+        self.days
+            .values()
+            .flat_map(|hours| hours.iter().copied())
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn render(&self, color: bool) -> Vec<String> {
+        let max = self.max_bucket();
+        let mut lines = vec![format!("{:<11}{}", "", "0     6     12    18    ")];
+
+        for (day, hours) in &self.days {
+            let cells: String = hours
+                .iter()
+                .map(|&minutes| {
+                    let level = heat_level(minutes, max);
+                    if color {
+                        HEAT_LEVELS[level].with(HEAT_COLORS[level]).to_string()
+                    } else {
+                        HEAT_LEVELS[level].to_string()
+                    }
+                })
+                .collect();
+            lines.push(format!("{:<11}{}", day.format("%a %m-%d"), cells));
+        }
+
+        lines.push(format!(
+            "{:<11}{} less  {} more   (active minutes per hour)",
+            "", HEAT_LEVELS[0], HEAT_LEVELS[4]
+        ));
+        lines
+    }
+}
+
+fn heat_level(minutes: u32, max: u32) -> usize {
+    if minutes == 0 || max == 0 {
+        return 0;
+    }
+    (minutes * 4).div_ceil(max).clamp(1, 4) as usize
+}
+
+/// Lines written per active hour across the sessions given each rating from 1
+/// to 5, with the number of sessions, or `None` for ratings never given.
+pub fn pace_by_rating(
+    entries: &[HistoryEntry],
+    rating: impl Fn(&HistoryEntry) -> Option<u8>,
+) -> [Option<(f64, usize)>; 5] {
+    let mut totals = [(0i64, 0i64, 0usize); 5];
+    for entry in entries.iter().filter(|entry| entry.active_time_seconds > 0) {
+        if let Some(rating) = rating(entry).filter(|rating| (1..=5).contains(rating)) {
+            let total = &mut totals[rating as usize - 1];
+            total.0 += i64::from(entry.lines_written);
+            total.1 += entry.active_time_seconds;
+            total.2 += 1;
+        }
+    }
+    totals.map(|(lines, secs, sessions)| {
+        (sessions > 0).then(|| (lines as f64 * 3600.0 / secs as f64, sessions))
+    })
+}
+
+pub fn window(days: u32) -> (NaiveDate, NaiveDate) {
+    let today = Local::now().date_naive();
+    let first_day = today - Duration::days(days.saturating_sub(1) as i64);
+    (first_day, today)
+}
+
+/// From Monday of the current week to today.
+pub fn week() -> (NaiveDate, NaiveDate) {
+    let today = Local::now().date_naive();
+    let monday = today - Duration::days(i64::from(today.weekday().num_days_from_monday()));
+    (monday, today)
+}
+
+/// From the first of the current month to today.
+pub fn month() -> (NaiveDate, NaiveDate) {
+    let today = Local::now().date_naive();
+    (today.with_day(1).unwrap_or(today), today)
+}
@@ -0,0 +1,1028 @@
+use crate::error::EgoError;
+use crate::gitignore::GitignoreFiles;
+use anyhow::Result;
+use chardetng::{EncodingDetector, Iso2022JpDetection, Utf8Detection};
+use clap::ValueEnum;
+use encoding_rs::{Encoding, UTF_8};
+use git2::Repository;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use similar::{Algorithm, DiffTag};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, Read};
+use std::iter::Sum;
+use std::ops::Add;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+pub const SESSION_FILE: &str = ".ego_session.json";
+pub const FINISHED_SESSION_FILE: &str = ".ego_session.done.json";
+/// The session is written here first and renamed over `SESSION_FILE`, so
+/// readers never see it half-written.
+pub const SAVING_SESSION_FILE: &str = ".ego_session.json.tmp";
+/// Files larger than this are skipped unless `max_file_size` says otherwise.
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 1024 * 1024;
+/// How much of a file is checked for NUL bytes to tell whether it is binary,
+/// as git does.
+const BINARY_CHECK_BYTES: u64 = 8000;
+
+/// Files marking a directory as the root of a package or crate.
+const SUBPROJECT_MARKERS: [&str; 2] = ["Cargo.toml", "package.json"];
+
+/// Directories never searched for subprojects, since they hold dependencies
+/// and build output with markers of their own.
+const SUBPROJECT_SKIP_DIRS: [&str; 2] = ["node_modules", "target"];
+
+const FINGERPRINT_SIZE: usize = 64;
+
+/// Identifies the algorithm behind `content_hash`, so hashes stored by a
+/// build using a different one are never compared with fresh ones. Bump it
+/// whenever the hash changes.
+pub const CONTENT_HASH_VERSION: u32 = 1;
+
+/// Where the tracked languages conventionally keep their tests.
+pub const DEFAULT_TEST_GLOBS: [&str; 13] = [
+    "**/tests/**",
+    "**/test/**",
+    "**/__tests__/**",
+    "**/*_test.rs",
+    "**/*_test.py",
+    "**/test_*.py",
+    "**/*.test.js",
+    "**/*.spec.js",
+    "**/*_test.c",
+    "**/*_test.cpp",
+    "**/*_test.cc",
+    "**/*Test.java",
+    "**/*Tests.java",
+];
+
+pub const TRACKED_EXTENSIONS: [&str; 17] = [
+    "rs", "txt", "md", "markdown", "py", "js", "html", "css", "c", "cpp", "h", "hpp", "java",
+    "json", "yaml", "yml", "toml",
+];
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanOptions {
+    pub generated_globs: Vec<String>,
+    pub only_globs: Vec<String>,
+    pub max_file_size: Option<u64>,
+    pub max_file_sizes: BTreeMap<String, u64>,
+    pub max_depth: Option<usize>,
+    pub detect_encoding: bool,
+    /// Count prose words in Markdown files, leaving out front matter and code.
+    pub prose_accurate: bool,
+    /// Subproject roots, relative to the project directory.
+    pub subprojects: Vec<PathBuf>,
+    pub detect_subprojects: bool,
+    /// Hash files outside the tracked set too, so that moves across the
+    /// ignore boundary aren't reported as a create or a delete.
+    pub follow_moves: bool,
+    /// Keep the hash of every line at start, so that the lines added during
+    /// the session can be found for the top terms.
+    pub analyze_terms: bool,
+    /// Count line endings in the character metric. Off by default, so that
+    /// a file's character count doesn't depend on its line endings or on a
+    /// trailing newline.
+    pub count_newline_as_char: bool,
+    /// With `count_newline_as_char`, count a final line without a newline as
+    /// if it had one, so adding or removing the trailing newline changes
+    /// nothing.
+    pub normalize_trailing_newline: bool,
+    /// Globs for test code, replacing `DEFAULT_TEST_GLOBS` when given.
+    pub test_globs: Vec<String>,
+    /// Extensions to track, replacing `TRACKED_EXTENSIONS` when given.
+    pub extensions: Vec<String>,
+    /// Files and directories never tracked, such as `node_modules` or
+    /// `target/**`.
+    pub ignore_globs: Vec<String>,
+    /// Leave out what the project's `.gitignore` files exclude.
+    pub respect_gitignore: bool,
+}
+
+/// Several measures of a file's size, all taken in one pass over its text.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContentMetrics {
+    pub lines: i32,
+    pub non_blank_lines: i32,
+    /// Runs of non-whitespace characters.
+    pub words: i32,
+    /// Identifiers, numbers and single punctuation characters, roughly as a
+    /// lexer for a C-like language would split the text.
+    pub tokens: i32,
+    /// Unicode scalar values, counting each line ending (`\n` or `\r\n`)
+    /// as one character only with `count_newline_as_char`.
+    pub chars: i32,
+}
+
+impl ContentMetrics {
+    /// Measures `text`. A final line counts as a line whether or not it ends
+    /// in a newline, so a trailing newline never changes the line-based
+    /// metrics. It only counts as a character with `count_newline_as_char`,
+    /// and then a missing one is counted too with `normalize_trailing_newline`.
+    pub fn measure(text: &str, options: &ScanOptions) -> Self {
+        let mut metrics = ContentMetrics::default();
+        if options.count_newline_as_char {
+            metrics.chars = text.matches('\n').count() as i32;
+            if options.normalize_trailing_newline && !text.is_empty() && !text.ends_with('\n') {
+                metrics.chars += 1;
+            }
+        }
+        for line in text.lines() {
+            metrics.lines += 1;
+            metrics.chars += line.chars().count() as i32;
+            if line.trim().is_empty() {
+                continue;
+            }
+            metrics.non_blank_lines += 1;
+            metrics.words += line.split_whitespace().count() as i32;
+
+            let mut in_word = false;
+            for c in line.chars() {
+                if c.is_alphanumeric() || c == '_' {
+                    if !in_word {
+                        metrics.tokens += 1;
+                    }
+                    in_word = true;
+                } else {
+                    if !c.is_whitespace() {
+                        metrics.tokens += 1;
+                    }
+                    in_word = false;
+                }
+            }
+        }
+        metrics
+    }
+
+    pub fn get(&self, metric: Metric) -> i32 {
+        match metric {
+            Metric::Lines => self.lines,
+            Metric::NonBlank => self.non_blank_lines,
+            Metric::Words => self.words,
+            Metric::Tokens => self.tokens,
+            Metric::Chars => self.chars,
+        }
+    }
+}
+
+impl Add for ContentMetrics {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        ContentMetrics {
+            lines: self.lines + other.lines,
+            non_blank_lines: self.non_blank_lines + other.non_blank_lines,
+            words: self.words + other.words,
+            tokens: self.tokens + other.tokens,
+            chars: self.chars + other.chars,
+        }
+    }
+}
+
+impl Sum for ContentMetrics {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(ContentMetrics::default(), Add::add)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Metric {
+    Lines,
+    NonBlank,
+    Words,
+    Tokens,
+    Chars,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 5] = [
+        Metric::Lines,
+        Metric::NonBlank,
+        Metric::Words,
+        Metric::Tokens,
+        Metric::Chars,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Metric::Lines => "Lines",
+            Metric::NonBlank => "Non-blank lines",
+            Metric::Words => "Words",
+            Metric::Tokens => "Tokens",
+            Metric::Chars => "Characters",
+        }
+    }
+}
+
+impl ScanOptions {
+    pub fn generated_matcher(&self) -> Result<GlobSet> {
+        build_globset(&self.generated_globs)
+    }
+
+    /// Matcher for files holding test code rather than production code.
+    pub fn test_matcher(&self) -> Result<GlobSet> {
+        if self.test_globs.is_empty() {
+            build_globset(&DEFAULT_TEST_GLOBS.map(String::from))
+        } else {
+            build_globset(&self.test_globs)
+        }
+    }
+
+    /// Matcher for the `--only` allow-list. When it is empty, files are
+    /// tracked by extension instead.
+    pub fn only_matcher(&self) -> Result<GlobSet> {
+        build_globset(&self.only_globs)
+    }
+
+    /// Whether a path relative to the project directory is shallow enough to
+    /// be scanned. Depth 0 is the project directory itself.
+    pub fn within_depth(&self, relative: &Path) -> bool {
+        let depth = relative.components().count().saturating_sub(1);
+        self.max_depth.is_none_or(|max_depth| depth <= max_depth)
+    }
+
+    /// Measures a file's contents, decoded as the options ask.
+    pub fn measure(&self, path: &Path) -> (ContentMetrics, Option<&'static str>) {
+        let Ok(bytes) = fs::read(path) else {
+            return (ContentMetrics::default(), None);
+        };
+        let (text, encoding) = self.decode(&bytes);
+        (ContentMetrics::measure(&text, self), encoding)
+    }
+
+    /// Decodes a file's contents. A byte order mark (UTF-8, UTF-16LE or
+    /// UTF-16BE) always decides the encoding and is left out of the text.
+    /// Otherwise the text is read as UTF-8 or, with `detect_encoding`, in its
+    /// detected encoding, which is returned too.
+    fn decode<'a>(&self, bytes: &'a [u8]) -> (Cow<'a, str>, Option<&'static str>) {
+        if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+            let (text, _) = encoding.decode_with_bom_removal(bytes);
+            return (text, self.detect_encoding.then(|| encoding.name()));
+        }
+        if !self.detect_encoding {
+            return (String::from_utf8_lossy(bytes), None);
+        }
+        let encoding = detect_encoding(bytes);
+        let (text, _, _) = encoding.decode(bytes);
+        (text, Some(encoding.name()))
+    }
+
+    /// Prose words in a Markdown file, or `None` for other files or when
+    /// `prose_accurate` is off.
+    pub fn prose_words(&self, path: &Path) -> Option<i32> {
+        if !self.prose_accurate || !is_markdown(path) {
+            return None;
+        }
+        let text = read_utf8(path)?;
+        Some(prose_words(&String::from_utf8_lossy(&text)) as i32)
+    }
+
+    /// The hash of every line of the file in order, decoded as `measure`
+    /// decodes it, for diffing against the file's later contents.
+    pub fn line_sequence(&self, path: &Path) -> Vec<u64> {
+        let Ok(bytes) = fs::read(path) else {
+            return Vec::new();
+        };
+        let (text, _) = self.decode(&bytes);
+        line_sequence(&text)
+    }
+
+    /// Reads a file once and takes every measure of its contents that a
+    /// snapshot records, each as the function computing it alone would.
+    /// A file that can't be read has no hash and is otherwise empty.
+    pub fn inspect(&self, path: &Path) -> FileContents {
+        let Ok(bytes) = fs::read(path) else {
+            return FileContents::default();
+        };
+        let (text, encoding) = self.decode(&bytes);
+        let utf8 = strip_bom(&bytes);
+        let all_line_hashes = line_hashes_of(&utf8);
+        FileContents {
+            metrics: ContentMetrics::measure(&text, self),
+            encoding,
+            hash: Some(hash_bytes(FNV128_OFFSET, &bytes)),
+            fingerprint: all_line_hashes
+                .iter()
+                .copied()
+                .take(FINGERPRINT_SIZE)
+                .collect(),
+            prose_words: (self.prose_accurate && is_markdown(path))
+                .then(|| prose_words(&String::from_utf8_lossy(&utf8)) as i32),
+            line_sequence: line_sequence(&text),
+            line_hashes: if self.analyze_terms {
+                all_line_hashes
+            } else {
+                Vec::new()
+            },
+        }
+    }
+
+    /// Size limit for a file, preferring a per-extension override over the
+    /// global `max_file_size`.
+    fn size_limit(&self, path: &Path) -> Option<u64> {
+        extension(path)
+            .and_then(|ext| self.max_file_sizes.get(&ext).copied())
+            .or(self.max_file_size)
+    }
+}
+
+/// The measures of a file's contents taken by `ScanOptions::inspect`.
+#[derive(Default)]
+pub struct FileContents {
+    pub metrics: ContentMetrics,
+    pub encoding: Option<&'static str>,
+    pub hash: Option<u128>,
+    pub fingerprint: Vec<u64>,
+    /// Only with `prose_accurate`, for Markdown files.
+    pub prose_words: Option<i32>,
+    /// Only with `analyze_terms`.
+    pub line_hashes: Vec<u64>,
+    pub line_sequence: Vec<u64>,
+}
+
+#[derive(Default)]
+pub struct Scan {
+    pub files: BTreeMap<PathBuf, ScannedFile>,
+    pub skipped: Vec<PathBuf>,
+}
+
+#[derive(Clone, Copy)]
+pub struct ScannedFile {
+    pub modified: SystemTime,
+    pub size: u64,
+}
+
+/// A time limit for a scan. Traversals check it between entries and give up
+/// with `EgoError::ScanTimeout` once it has passed, so they never block
+/// for longer than about one file's worth of work past the limit.
+pub struct ScanDeadline {
+    limit: Option<(Instant, Duration)>,
+}
+
+impl ScanDeadline {
+    pub const NONE: ScanDeadline = ScanDeadline { limit: None };
+
+    pub fn after(timeout: Option<Duration>) -> Self {
+        ScanDeadline {
+            limit: timeout.map(|timeout| (Instant::now() + timeout, timeout)),
+        }
+    }
+
+    pub fn check(&self) -> Result<(), EgoError> {
+        match self.limit {
+            Some((at, timeout)) if Instant::now() >= at => Err(EgoError::ScanTimeout(timeout)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Decides which files under a project directory are tracked, for scans as
+/// well as for the file watcher and branch comparisons.
+pub struct FileFilter {
+    only: GlobSet,
+    extensions: Vec<String>,
+    ignore: GlobSet,
+    /// Where `.gitignore` rules come from, with `respect_gitignore`.
+    gitignore: Option<Gitignore>,
+}
+
+enum Gitignore {
+    /// The repository the project is in and the project directory's path
+    /// within its working tree.
+    Repo(Repository, PathBuf),
+    /// Outside a repository, the project's own `.gitignore` files.
+    Files(GitignoreFiles),
+}
+
+impl FileFilter {
+    pub fn new(root: &Path, options: &ScanOptions) -> Result<Self> {
+        let extensions = if options.extensions.is_empty() {
+            TRACKED_EXTENSIONS.map(String::from).to_vec()
+        } else {
+            options
+                .extensions
+                .iter()
+                .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                .collect()
+        };
+        let gitignore = options.respect_gitignore.then(|| {
+            let repo = || {
+                let repo = Repository::discover(root).ok()?;
+                let workdir = fs::canonicalize(repo.workdir()?).ok()?;
+                let prefix = fs::canonicalize(root)
+                    .ok()?
+                    .strip_prefix(&workdir)
+                    .ok()?
+                    .to_path_buf();
+                Some(Gitignore::Repo(repo, prefix))
+            };
+            repo().unwrap_or_else(|| Gitignore::Files(GitignoreFiles::new(root)))
+        });
+        Ok(FileFilter {
+            only: options.only_matcher()?,
+            extensions,
+            ignore: build_globset(&options.ignore_globs)?,
+            gitignore,
+        })
+    }
+
+    /// Whether a scan descends into a directory, given relative to the
+    /// project directory.
+    pub fn visits_dir(&self, relative: &Path) -> bool {
+        relative
+            .file_name()
+            .is_none_or(|name| !name.to_string_lossy().starts_with('.'))
+            && !self.is_ignored(relative, true)
+    }
+
+    /// Whether a file, given relative to the project directory, is tracked.
+    pub fn tracks(&self, relative: &Path) -> bool {
+        let in_hidden_dir = relative.parent().is_some_and(|parent| {
+            parent
+                .components()
+                .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+        });
+
+        !in_hidden_dir
+            && relative
+                .file_name()
+                .is_some_and(|name| !is_session_file(name))
+            && self.matches_kind(relative)
+            && !self.is_ignored(relative, false)
+    }
+
+    /// An allow-list given with `--only` replaces the extension list.
+    fn matches_kind(&self, relative: &Path) -> bool {
+        if self.only.is_empty() {
+            extension(relative).is_some_and(|ext| self.extensions.contains(&ext))
+        } else {
+            self.only.is_match(relative)
+        }
+    }
+
+    /// Whether the path or one of its parent directories matches an ignore
+    /// glob, or git ignores it.
+    fn is_ignored(&self, relative: &Path, is_dir: bool) -> bool {
+        if !self.ignore.is_empty()
+            && relative
+                .ancestors()
+                .filter(|ancestor| !ancestor.as_os_str().is_empty())
+                .any(|ancestor| self.ignore.is_match(ancestor))
+        {
+            return true;
+        }
+        match &self.gitignore {
+            Some(Gitignore::Repo(repo, prefix)) => {
+                let mut path = prefix.join(relative).to_string_lossy().into_owned();
+                // libgit2 only applies directory patterns such as `target/`
+                // to paths marked as directories.
+                if is_dir {
+                    path.push('/');
+                }
+                repo.is_path_ignored(&path).unwrap_or(false)
+            }
+            Some(Gitignore::Files(files)) => files.is_ignored(relative, is_dir),
+            None => false,
+        }
+    }
+}
+
+struct Walk<'a> {
+    root: &'a Path,
+    options: &'a ScanOptions,
+    filter: FileFilter,
+}
+
+pub fn scan_files(dir: &Path, options: &ScanOptions) -> Result<Scan> {
+    scan_files_until(dir, options, &ScanDeadline::NONE)
+}
+
+/// Like `scan_files`, but gives up once `deadline` has passed. Directories
+/// are walked from an explicit stack rather than recursively, checking the
+/// deadline before each entry.
+pub fn scan_files_until(
+    dir: &Path,
+    options: &ScanOptions,
+    deadline: &ScanDeadline,
+) -> Result<Scan> {
+    let walk = Walk {
+        root: dir,
+        options,
+        filter: FileFilter::new(dir, options)?,
+    };
+    let mut scan = Scan::default();
+    let mut visited = HashSet::new();
+    let mut pending = vec![(dir.to_path_buf(), 0)];
+    while let Some((dir, depth)) = pending.pop() {
+        if !first_visit(&mut visited, &dir) {
+            continue;
+        }
+        for entry in fs::read_dir(&dir)? {
+            deadline.check()?;
+            let entry = entry?;
+            let path = entry.path();
+
+            let relative = path.strip_prefix(walk.root).unwrap_or(&path);
+
+            if path.is_dir() {
+                if walk
+                    .options
+                    .max_depth
+                    .is_none_or(|max_depth| depth < max_depth)
+                    && walk.filter.visits_dir(relative)
+                {
+                    pending.push((path, depth + 1));
+                }
+            } else if path.is_file() && walk.filter.tracks(relative) {
+                let metadata = entry.metadata()?;
+                if walk
+                    .options
+                    .size_limit(&path)
+                    .is_some_and(|limit| metadata.len() > limit)
+                    || is_binary(&path)
+                {
+                    scan.skipped.push(path);
+                    continue;
+                }
+
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                scan.files.insert(
+                    path,
+                    ScannedFile {
+                        modified,
+                        size: metadata.len(),
+                    },
+                );
+            }
+        }
+    }
+    Ok(scan)
+}
+
+/// Content hashes of every file under `dir` that a scan leaves out, such as
+/// files in hidden directories, below `max_depth` or with an untracked
+/// extension. Only `.git`, the session file and files over the size limit
+/// are skipped.
+pub fn untracked_hashes<T>(
+    dir: &Path,
+    tracked: &BTreeMap<PathBuf, T>,
+    options: &ScanOptions,
+    deadline: &ScanDeadline,
+) -> Result<BTreeMap<PathBuf, u128>> {
+    fn visit_dirs<T>(
+        dir: &Path,
+        tracked: &BTreeMap<PathBuf, T>,
+        options: &ScanOptions,
+        deadline: &ScanDeadline,
+        visited: &mut HashSet<PathBuf>,
+        hashes: &mut BTreeMap<PathBuf, u128>,
+    ) -> Result<()> {
+        if !first_visit(visited, dir) {
+            return Ok(());
+        }
+        for entry in fs::read_dir(dir)? {
+            deadline.check()?;
+            let entry = entry?;
+            let path = entry.path();
+            if path.is_dir() {
+                if path.file_name().is_some_and(|name| name != ".git") {
+                    visit_dirs(&path, tracked, options, deadline, visited, hashes)?;
+                }
+            } else if path.is_file()
+                && !tracked.contains_key(&path)
+                && path.file_name().is_some_and(|name| !is_session_file(name))
+                && options.size_limit(&path).is_none_or(|limit| {
+                    entry
+                        .metadata()
+                        .is_ok_and(|metadata| metadata.len() <= limit)
+                })
+            {
+                if let Some(hash) = content_hash(&path) {
+                    hashes.insert(path, hash);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    let mut hashes = BTreeMap::new();
+    visit_dirs(
+        dir,
+        tracked,
+        options,
+        deadline,
+        &mut HashSet::new(),
+        &mut hashes,
+    )?;
+    Ok(hashes)
+}
+
+/// Subproject roots for a project, relative to `dir`: the configured ones
+/// plus, with `detect_subprojects`, every directory below `dir` containing a
+/// `Cargo.toml` or `package.json`.
+pub fn subproject_roots(dir: &Path, options: &ScanOptions) -> Result<Vec<PathBuf>> {
+    fn visit_dirs(
+        dir: &Path,
+        relative: &Path,
+        visited: &mut HashSet<PathBuf>,
+        roots: &mut Vec<PathBuf>,
+    ) -> io::Result<()> {
+        if !first_visit(visited, dir) {
+            return Ok(());
+        }
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let Some(name) = path.file_name().map(|name| name.to_string_lossy()) else {
+                continue;
+            };
+            if !path.is_dir()
+                || name.starts_with('.')
+                || SUBPROJECT_SKIP_DIRS.contains(&name.as_ref())
+            {
+                continue;
+            }
+
+            let relative = relative.join(name.as_ref());
+            if SUBPROJECT_MARKERS
+                .iter()
+                .any(|marker| path.join(marker).is_file())
+            {
+                roots.push(relative.clone());
+            }
+            visit_dirs(&path, &relative, visited, roots)?;
+        }
+        Ok(())
+    }
+
+    let mut roots = options.subprojects.clone();
+    if options.detect_subprojects && dir.is_dir() {
+        visit_dirs(dir, Path::new(""), &mut HashSet::new(), &mut roots)?;
+    }
+    roots.sort();
+    roots.dedup();
+    Ok(roots)
+}
+
+/// Whether `name` is one of ego's own session files, which are never counted.
+fn is_session_file(name: &OsStr) -> bool {
+    name == SESSION_FILE || name == FINISHED_SESSION_FILE || name == SAVING_SESSION_FILE
+}
+
+/// Records `dir` as walked and reports whether it hadn't been already.
+/// Directories are compared by canonical path, so a symlink pointing back up
+/// the tree is only followed until it reaches a directory seen before.
+fn first_visit(visited: &mut HashSet<PathBuf>, dir: &Path) -> bool {
+    visited.insert(fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf()))
+}
+
+/// A 128-bit FNV-1a hash of the file's contents. Unlike `DefaultHasher`, the
+/// result is stable across builds, so it can be stored in the session file,
+/// and it is wide enough that an edited file won't collide with its original.
+/// The file is read in blocks rather than all at once.
+pub fn content_hash(path: &Path) -> Option<u128> {
+    let mut file = fs::File::open(path).ok()?;
+    let mut buffer = [0; 64 * 1024];
+    let mut hash = FNV128_OFFSET;
+    loop {
+        let read = match file.read(&mut buffer) {
+            Ok(0) => return Some(hash),
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => return None,
+        };
+        hash = hash_bytes(hash, &buffer[..read]);
+    }
+}
+
+const FNV128_OFFSET: u128 = 0x6c62_272e_07bb_0142_62b8_2175_6295_c58d;
+
+/// Continues a 128-bit FNV-1a `hash` over `bytes`.
+fn hash_bytes(hash: u128, bytes: &[u8]) -> u128 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ u128::from(*byte)).wrapping_mul(0x0000_0000_0100_0000_0000_0000_0000_013b)
+    })
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// A compact sketch of a file's contents for estimating how similar two
+/// files are without keeping either: the smallest `FINGERPRINT_SIZE` hashes
+/// of its distinct non-blank lines (a bottom-k MinHash).
+pub fn fingerprint(path: &Path) -> Vec<u64> {
+    line_hashes(path)
+        .into_iter()
+        .take(FINGERPRINT_SIZE)
+        .collect()
+}
+
+/// The sorted, distinct hashes of a file's non-blank lines, ignoring
+/// surrounding whitespace.
+pub fn line_hashes(path: &Path) -> Vec<u64> {
+    read_utf8(path).map_or_else(Vec::new, |bytes| line_hashes_of(&bytes))
+}
+
+fn line_hashes_of(bytes: &[u8]) -> Vec<u64> {
+    let hashes: BTreeSet<u64> = bytes
+        .split(|byte| *byte == b'\n')
+        .map(|line| line.trim_ascii())
+        .filter(|line| !line.is_empty())
+        .map(line_hash)
+        .collect();
+    hashes.into_iter().collect()
+}
+
+fn line_sequence(text: &str) -> Vec<u64> {
+    text.lines().map(|line| fnv1a(line.as_bytes())).collect()
+}
+
+/// The hash of a line as stored by `line_hashes`. The line should already
+/// be trimmed.
+pub fn line_hash(line: &[u8]) -> u64 {
+    fnv1a(line)
+}
+
+/// Lines added and removed between two line sequences, by a Myers diff.
+pub fn diff_line_counts(old: &[u64], new: &[u64]) -> (i32, i32) {
+    similar::capture_diff_slices(Algorithm::Myers, old, new)
+        .iter()
+        .fold((0, 0), |(added, removed), op| match op.tag() {
+            DiffTag::Equal => (added, removed),
+            _ => (
+                added + op.new_range().len() as i32,
+                removed + op.old_range().len() as i32,
+            ),
+        })
+}
+
+/// Estimates the Jaccard similarity of the line sets behind two
+/// fingerprints, from `0.0` (nothing in common) to `1.0`.
+pub fn similarity(a: &[u64], b: &[u64]) -> f64 {
+    let a: BTreeSet<u64> = a.iter().copied().collect();
+    let b: BTreeSet<u64> = b.iter().copied().collect();
+    let union: Vec<u64> = a.union(&b).copied().take(FINGERPRINT_SIZE).collect();
+    if union.is_empty() {
+        return 0.0;
+    }
+    let shared = union
+        .iter()
+        .filter(|hash| a.contains(hash) && b.contains(hash))
+        .count();
+    shared as f64 / union.len() as f64
+}
+
+/// Reads a file as UTF-8 bytes for splitting into lines. A file starting
+/// with a byte order mark, such as the UTF-16 files some Windows tools
+/// write, is decoded from the encoding the mark names, without the mark.
+pub fn read_utf8(path: &Path) -> Option<Vec<u8>> {
+    fs::read(path)
+        .ok()
+        .map(|bytes| strip_bom(&bytes).into_owned())
+}
+
+fn strip_bom(bytes: &[u8]) -> Cow<'_, [u8]> {
+    match Encoding::for_bom(bytes) {
+        Some((encoding, _)) => Cow::Owned(
+            encoding
+                .decode_with_bom_removal(bytes)
+                .0
+                .into_owned()
+                .into_bytes(),
+        ),
+        None => Cow::Borrowed(bytes),
+    }
+}
+
+/// Whether a file looks binary: it has a NUL byte near the start and no byte
+/// order mark, which would make it UTF-16 text.
+fn is_binary(path: &Path) -> bool {
+    let mut start = Vec::new();
+    let read =
+        fs::File::open(path).and_then(|file| file.take(BINARY_CHECK_BYTES).read_to_end(&mut start));
+    read.is_ok() && Encoding::for_bom(&start).is_none() && start.contains(&0)
+}
+
+fn detect_encoding(bytes: &[u8]) -> &'static Encoding {
+    if std::str::from_utf8(bytes).is_ok() {
+        return UTF_8;
+    }
+    let mut detector = EncodingDetector::new(Iso2022JpDetection::Allow);
+    detector.feed(bytes, true);
+    detector.guess(None, Utf8Detection::Allow)
+}
+
+fn build_globset(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
+    }
+    Ok(builder.build()?)
+}
+
+fn is_markdown(path: &Path) -> bool {
+    matches!(extension(path).as_deref(), Some("md" | "markdown"))
+}
+
+/// Counts the words of Markdown prose, skipping YAML front matter, fenced
+/// code blocks and inline code spans.
+pub fn prose_words(text: &str) -> usize {
+    let mut lines = text.lines().peekable();
+    if lines.peek().is_some_and(|line| line.trim_end() == "---") {
+        lines.next();
+        for line in lines.by_ref() {
+            if matches!(line.trim_end(), "---" | "...") {
+                break;
+            }
+        }
+    }
+
+    let mut fence: Option<&str> = None;
+    let mut words = 0;
+    for line in lines {
+        let trimmed = line.trim_start();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+        if trimmed.starts_with("```") {
+            fence = Some("```");
+            continue;
+        }
+        if trimmed.starts_with("~~~") {
+            fence = Some("~~~");
+            continue;
+        }
+
+        words += line
+            .split('`')
+            .step_by(2)
+            .flat_map(str::split_whitespace)
+            .filter(|word| word.chars().any(char::is_alphanumeric))
+            .count();
+    }
+    words
+}
+
+/// Display name of the language of a tracked file, based on its extension.
+pub fn language(path: &Path) -> &'static str {
+    match extension(path).as_deref() {
+        Some("rs") => "Rust",
+        Some("py") => "Python",
+        Some("js") => "JavaScript",
+        Some("html") => "HTML",
+        Some("css") => "CSS",
+        Some("c" | "h") => "C",
+        Some("cpp" | "hpp") => "C++",
+        Some("java") => "Java",
+        Some("json") => "JSON",
+        Some("yaml" | "yml") => "YAML",
+        Some("toml") => "TOML",
+        Some("md" | "markdown") => "Markdown",
+        Some("txt") => "Text",
+        _ => "Other",
+    }
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|ext| ext.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn inspect_matches_separate_reads() {
+        let dir = TempDir::new();
+        let path = dir.write(
+            "notes.md",
+            "# Title\n\nSome prose here.\n\n```\nlet x = 1;\n```\n",
+        );
+        let options = ScanOptions {
+            prose_accurate: true,
+            analyze_terms: true,
+            ..ScanOptions::default()
+        };
+
+        let contents = options.inspect(&path);
+        assert_eq!(contents.metrics, options.measure(&path).0);
+        assert_eq!(contents.hash, content_hash(&path));
+        assert_eq!(contents.fingerprint, fingerprint(&path));
+        assert_eq!(contents.prose_words, options.prose_words(&path));
+        assert_eq!(contents.line_hashes, line_hashes(&path));
+        assert_eq!(contents.line_sequence, options.line_sequence(&path));
+    }
+
+    #[test]
+    fn gitignore_applies_outside_repository() {
+        let dir = TempDir::new();
+        dir.write(".gitignore", "target/\n");
+        let main = dir.write("main.rs", "fn main() {}\n");
+        dir.write("target/out.rs", "fn out() {}\n");
+
+        let options = ScanOptions {
+            respect_gitignore: true,
+            ..ScanOptions::default()
+        };
+        let scan = scan_files(dir.path(), &options).unwrap();
+        assert_eq!(scan.files.keys().collect::<Vec<_>>(), vec![&main]);
+    }
+
+    #[test]
+    fn utf16_file_with_bom_is_decoded() {
+        let dir = TempDir::new();
+        let text = "fn main() {\r\n    let größe = 1;\r\n}\r\n";
+        let mut bytes = vec![0xff, 0xfe];
+        bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        let path = dir.write("main.rs", bytes);
+
+        let (metrics, _) = ScanOptions::default().measure(&path);
+        assert_eq!(metrics.lines, 3);
+        // The BOM and line endings aren't characters; "ö" and "ß" are one each.
+        assert_eq!(metrics.chars, 11 + 18 + 1);
+        assert_eq!(metrics.words, 8);
+        assert!(!is_binary(&path));
+    }
+
+    #[test]
+    fn prose_words_skip_front_matter_code_and_inline_code() {
+        let dir = TempDir::new();
+        let text = "---\ntitle: Notes on parsing\ntags: [rust]\n---\n\
+                    # Parsing notes\n\
+                    The `parse` function returns a tree.\n\
+                    ```rust\nfn parse() -> Tree { todo!() }\n```\n\
+                    ~~~\nmore code here\n~~~\n\
+                    That is all.\n";
+        let path = dir.write("notes.md", text);
+        let options = ScanOptions {
+            prose_accurate: true,
+            ..ScanOptions::default()
+        };
+
+        assert_eq!(prose_words(text), 10);
+        assert_eq!(options.prose_words(&path), Some(10));
+        assert_eq!(ScanOptions::default().prose_words(&path), None);
+        let source = dir.write("notes.rs", text);
+        assert_eq!(options.prose_words(&source), None);
+    }
+
+    #[test]
+    fn chars_count_newlines_only_when_asked() {
+        let dir = TempDir::new();
+        let path = dir.write("greeting.md", "héllo\r\nwörld\n");
+        let counting_newlines = ScanOptions {
+            count_newline_as_char: true,
+            ..ScanOptions::default()
+        };
+
+        assert_eq!(ScanOptions::default().measure(&path).0.chars, 10);
+        assert_eq!(counting_newlines.measure(&path).0.chars, 12);
+    }
+
+    #[test]
+    fn trailing_newline_only_counts_as_a_char() {
+        let dir = TempDir::new();
+        let with = dir.write("with.rs", "fn a() {}\nfn b() {}\n");
+        let without = dir.write("without.rs", "fn a() {}\nfn b() {}");
+        let chars = |options: &ScanOptions, path: &Path| options.measure(path).0.chars;
+        let counting_newlines = ScanOptions {
+            count_newline_as_char: true,
+            ..ScanOptions::default()
+        };
+        let normalized = ScanOptions {
+            normalize_trailing_newline: true,
+            ..counting_newlines.clone()
+        };
+
+        let defaults = ScanOptions::default();
+        assert_eq!(defaults.measure(&with).0, defaults.measure(&without).0);
+        assert_eq!(defaults.measure(&with).0.lines, 2);
+        assert_eq!(chars(&counting_newlines, &with), 20);
+        assert_eq!(chars(&counting_newlines, &without), 19);
+        assert_eq!(chars(&normalized, &with), 20);
+        assert_eq!(chars(&normalized, &without), 20);
+
+        // Neither the line diff nor move detection sees a difference.
+        let (with_lines, without_lines) = (
+            defaults.line_sequence(&with),
+            defaults.line_sequence(&without),
+        );
+        assert_eq!(diff_line_counts(&with_lines, &without_lines), (0, 0));
+        assert_eq!(similarity(&fingerprint(&with), &fingerprint(&without)), 1.0);
+        assert_ne!(content_hash(&with), content_hash(&without));
+    }
+}
@@ -1,98 +1,1730 @@
+use crate::error::EgoError;
+use crate::machine::Machine;
+use crate::plugin::CommandMetric;
+use crate::scan::{
+    self, ContentMetrics, Scan, ScanDeadline, ScanOptions, ScannedFile, FINISHED_SESSION_FILE,
+    SAVING_SESSION_FILE, SESSION_FILE,
+};
+use crate::terms::{self, TermCount};
+use crate::tokei::TokeiCounts;
 use anyhow::Result;
 use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use globset::GlobSet;
 use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
 use std::fs;
-use std::io::{self, BufRead};
-use std::path::{Path, PathBuf};
+use std::io;
+use std::mem;
+use std::path::{self, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+pub const IDLE_THRESHOLD_SECS: i64 = 300;
+const MAX_ACTIVITY_SAMPLES: usize = 2880;
+const SAVE_ATTEMPTS: u32 = 4;
+const SAVE_BACKOFF: Duration = Duration::from_millis(50);
 
 #[derive(Serialize, Deserialize)]
 pub struct Session {
+    #[serde(default)]
+    pub id: String,
+    /// When the initial scan finished, so the time spent scanning a large
+    /// project isn't counted as part of the session.
     pub start_time: DateTime<Local>,
+    /// Set when the session is ended, so that a finalized session holds
+    /// everything needed to render its report.
+    #[serde(default)]
+    pub end_time: Option<DateTime<Local>>,
     pub project_directory: PathBuf,
+    /// Where `ego start` ran, which `project_directory` and the file paths
+    /// are relative to. Older sessions lack it and were saved there.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_directory: Option<PathBuf>,
+    /// The session file, inside the project directory.
+    #[serde(skip)]
+    file: PathBuf,
+    /// The session file's modification time when this process last read or
+    /// wrote it.
+    #[serde(skip)]
+    file_modified: Cell<Option<SystemTime>>,
+    #[serde(default)]
+    pub project_name: String,
     pub initial_line_count: i32,
     pub final_line_count: Option<i32>,
     pub lines_written: Option<i32>,
+    #[serde(default)]
+    pub initial_metrics: ContentMetrics,
+    #[serde(default)]
+    pub final_metrics: Option<ContentMetrics>,
+    #[serde(default)]
+    pub initial_prose_words: Option<i32>,
+    #[serde(default)]
+    pub final_prose_words: Option<i32>,
+    #[serde(default)]
+    pub files: BTreeMap<PathBuf, FileSnapshot>,
+    #[serde(default)]
+    pub files_created: Vec<PathBuf>,
+    #[serde(default)]
+    pub files_modified: Vec<PathBuf>,
+    #[serde(default)]
+    pub files_deleted: Vec<PathBuf>,
+    #[serde(default)]
+    pub files_renamed: Vec<Rename>,
+    /// Tracked files moved to a path outside the tracked set.
+    #[serde(default)]
+    pub files_moved_out: Vec<Rename>,
+    /// Files moved into the tracked set from a path outside it.
+    #[serde(default)]
+    pub files_moved_in: Vec<Rename>,
+    #[serde(default)]
+    pub subprojects: Vec<SubprojectChanges>,
+    /// The most frequent words on the lines added during the session,
+    /// computed with `analyze_terms`.
+    #[serde(default)]
+    pub top_terms: Vec<TermCount>,
+    #[serde(default)]
+    pub file_changes: Vec<FileChange>,
+    #[serde(default)]
+    pub options: ScanOptions,
+    /// Content hashes of the untracked files at the start, recorded with
+    /// `follow_moves`. Stored under a new key since the switch to 128-bit
+    /// hashes, so older sessions don't compare hashes of different widths.
+    #[serde(default, rename = "untracked_hashes")]
+    pub untracked_files: BTreeMap<PathBuf, u128>,
+    /// The `scan::CONTENT_HASH_VERSION` the stored hashes were made with.
+    /// Sessions saved before it was recorded used version 1.
+    #[serde(default = "first_hash_version")]
+    pub hash_version: u32,
+    #[serde(default)]
+    pub generated_files_changed: Vec<PathBuf>,
+    #[serde(default)]
+    pub generated_lines_written: i32,
+    /// The part of `lines_written` in files matching the test globs.
+    #[serde(default)]
+    pub test_lines_written: i32,
+    #[serde(default)]
+    pub files_skipped: Vec<PathBuf>,
+    #[serde(default)]
+    pub small_files_omitted: Vec<PathBuf>,
+    #[serde(default)]
+    pub active_time_seconds: i64,
+    #[serde(default)]
+    pub first_activity: Option<DateTime<Local>>,
+    #[serde(default)]
+    pub last_activity: Option<DateTime<Local>>,
+    #[serde(default)]
+    pub breaks: Vec<Break>,
+    /// Gaps between activity longer than this count as breaks. Sessions
+    /// started before it was configurable used the built-in threshold.
+    #[serde(default = "default_idle_threshold")]
+    pub idle_threshold_secs: i64,
+    /// Set by `ego pause` until `ego resume`. Paused time counts as neither
+    /// active nor idle.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paused_at: Option<DateTime<Local>>,
+    /// Time spent in earlier pauses, in seconds.
+    #[serde(default)]
+    pub paused_secs: i64,
+    #[serde(default)]
+    pub activity_samples: Vec<DateTime<Local>>,
+    /// Tracked files created, modified or removed at any point while
+    /// watching for changes, including those that ended up unchanged.
+    #[serde(default)]
+    pub files_touched: BTreeSet<PathBuf>,
+    /// How the user rated their mood and energy, from 1 to 5.
+    #[serde(default)]
+    pub mood: Option<u8>,
+    #[serde(default)]
+    pub energy: Option<u8>,
+    /// Targets set with `--goal-lines` and `--goal-minutes`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub goal_lines: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub goal_minutes: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command_metric: Option<CommandMetric>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokei: Option<TokeiCounts>,
+    /// Where the session took place, with `--record-machine`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub machine: Option<Machine>,
+}
+
+fn first_hash_version() -> u32 {
+    1
+}
+
+fn default_idle_threshold() -> i64 {
+    IDLE_THRESHOLD_SECS
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Break {
+    pub started_at: DateTime<Local>,
+    pub duration_secs: i64,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Created,
+    Modified,
+    Deleted,
+    Renamed,
+}
+
+/// A deleted file paired with a created one whose contents are similar
+/// enough that it was most likely moved, possibly with edits.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Rename {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    /// Estimated share of lines the two versions have in common.
+    pub similarity: f64,
+    /// Estimated number of lines changed along with the move.
+    pub churn: i32,
+}
+
+/// How `end()` decides that a file present at both start and end was
+/// modified.
+///
+/// - `Hash` compares content hashes. It is the most accurate (touching or
+///   re-saving a file unchanged doesn't count) but reads every file whose
+///   modification time or size changed again.
+/// - `Mtime` compares modification times. It needs no reads, but counts files
+///   that were saved without changes, and misses edits that restore the
+///   original timestamp.
+/// - `Size` compares byte sizes. It needs no reads either, but misses edits
+///   that keep the size the same, such as replacing a character.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ModifiedDetection {
+    Hash,
+    Mtime,
+    Size,
+}
+
+impl ModifiedDetection {
+    fn is_modified(self, path: &Path, snapshot: &FileSnapshot, current: &ScannedFile) -> bool {
+        match self {
+            ModifiedDetection::Hash => match snapshot.hash {
+                Some(_) if snapshot.unchanged_on_disk(current) => false,
+                Some(hash) => scan::content_hash(path) != Some(hash),
+                None => snapshot.modified != current.modified,
+            },
+            ModifiedDetection::Mtime => snapshot.modified != current.modified,
+            ModifiedDetection::Size => snapshot.size != Some(current.size),
+        }
+    }
+}
+
+pub struct EndOptions {
+    /// Write the finalized session to `.ego_session.done.json`.
+    pub keep: bool,
+    /// Compute the final stats but leave the session running and its files
+    /// untouched.
+    pub dry_run: bool,
+    pub modified_detection: ModifiedDetection,
+    /// Leave files with fewer lines than this, both before and after the
+    /// session, out of the change lists. They still count towards the totals.
+    pub min_file_lines: i32,
+    /// How similar a deleted and a created file must be, from `0.0` to `1.0`,
+    /// to be reported as a rename.
+    pub rename_threshold: f64,
+}
+
+/// Line and file changes within one subproject of the project.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SubprojectChanges {
+    /// The subproject's root, relative to the project directory.
+    pub path: PathBuf,
+    pub initial_line_count: i32,
+    pub final_line_count: i32,
+    pub files_changed: usize,
+}
+
+impl SubprojectChanges {
+    pub fn lines_written(&self) -> i32 {
+        self.final_line_count - self.initial_line_count
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FileChange {
+    pub path: PathBuf,
+    pub kind: ChangeKind,
+    pub initial_lines: i32,
+    pub final_lines: i32,
+    #[serde(default)]
+    pub initial_chars: i32,
+    #[serde(default)]
+    pub final_chars: i32,
+    #[serde(default)]
+    pub lines_added: i32,
+    #[serde(default)]
+    pub lines_removed: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub renamed_from: Option<PathBuf>,
+}
+
+impl FileChange {
+    pub fn delta(&self) -> i32 {
+        self.final_lines - self.initial_lines
+    }
+
+    /// Lines added and removed. Changes recorded before these were counted
+    /// have only the net change to go on.
+    pub fn added_removed(&self) -> (i32, i32) {
+        if self.lines_added == 0 && self.lines_removed == 0 {
+            (self.delta().max(0), (-self.delta()).max(0))
+        } else {
+            (self.lines_added, self.lines_removed)
+        }
+    }
+
+    pub fn char_delta(&self) -> i32 {
+        self.final_chars - self.initial_chars
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FileSnapshot {
+    pub lines: i32,
+    pub modified: SystemTime,
+    #[serde(default)]
+    pub size: Option<u64>,
+    /// Older sessions stored a 64-bit hash under `hash`; those snapshots
+    /// have none and fall back to comparing modification times.
+    #[serde(default, rename = "content_hash")]
+    pub hash: Option<u128>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fingerprint: Vec<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prose_words: Option<i32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub line_hashes: Vec<u64>,
+    /// Every line's hash in order, where `line_hashes` is a sorted set.
+    /// Sessions started before this was kept have none.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub line_sequence: Vec<u64>,
+    #[serde(default)]
+    pub metrics: ContentMetrics,
+}
+
+impl FileSnapshot {
+    /// Whether the file still has the modification time and size it had at
+    /// start, in which case its contents are taken to be unchanged rather
+    /// than read again. Only an edit that keeps the size and restores the
+    /// timestamp gets past this.
+    fn unchanged_on_disk(&self, current: &ScannedFile) -> bool {
+        self.modified == current.modified && self.size.is_none_or(|size| size == current.size)
+    }
 }
 
 impl Session {
-    pub fn new(project_directory: &str) -> Result<Self> {
+    /// Lists the files a session started in `project_directory` would track,
+    /// without reading them, until `deadline` at most. This is cheap enough
+    /// to ask whether to go ahead before `new` snapshots them.
+    pub fn survey(
+        project_directory: &str,
+        options: &ScanOptions,
+        deadline: &ScanDeadline,
+    ) -> Result<Scan> {
+        let project_path = Path::new(project_directory);
+        if !project_path.is_dir() {
+            return Err(EgoError::DirectoryMissing(project_path.to_path_buf()).into());
+        }
+        scan::scan_files_until(project_path, options, deadline)
+    }
+
+    /// Starts a session with the files found by `survey`, snapshotting them
+    /// until `deadline` at most.
+    pub fn new(
+        project_directory: &str,
+        project_name: Option<String>,
+        options: ScanOptions,
+        survey: Scan,
+        deadline: &ScanDeadline,
+    ) -> Result<Self> {
         let project_path = PathBuf::from(project_directory);
-        let initial_lines = Self::count_all_lines(&project_path)?;
+        let project_name = project_name.unwrap_or_else(|| default_project_name(&project_path));
+        let generated = options.generated_matcher()?;
+        let files = Self::snapshot_files(survey, &options, deadline)?;
+        let untracked_files = if options.follow_moves {
+            scan::untracked_hashes(&project_path, &files, &options, deadline)?
+        } else {
+            BTreeMap::new()
+        };
+        let start_time = Local::now();
+        let initial_lines = files
+            .iter()
+            .filter(|(path, _)| !Self::matches(&generated, &project_path, path))
+            .map(|(_, file)| file.lines)
+            .sum();
+        let initial_metrics = files
+            .iter()
+            .filter(|(path, _)| !Self::matches(&generated, &project_path, path))
+            .map(|(_, file)| file.metrics)
+            .sum();
+        let initial_prose_words = options.prose_accurate.then(|| {
+            files
+                .iter()
+                .filter(|(path, _)| !Self::matches(&generated, &project_path, path))
+                .filter_map(|(_, file)| file.prose_words)
+                .sum()
+        });
 
         Ok(Session {
-            start_time: Local::now(),
+            id: session_id(&start_time),
+            start_time,
+            end_time: None,
+            file: path::absolute(project_path.join(SESSION_FILE))?,
+            file_modified: Cell::new(None),
             project_directory: project_path,
+            working_directory: Some(env::current_dir()?),
+            project_name,
             initial_line_count: initial_lines,
             final_line_count: None,
             lines_written: None,
+            initial_metrics,
+            final_metrics: None,
+            initial_prose_words,
+            final_prose_words: None,
+            files,
+            files_created: Vec::new(),
+            files_modified: Vec::new(),
+            files_deleted: Vec::new(),
+            files_renamed: Vec::new(),
+            files_moved_out: Vec::new(),
+            files_moved_in: Vec::new(),
+            subprojects: Vec::new(),
+            top_terms: Vec::new(),
+            file_changes: Vec::new(),
+            options,
+            untracked_files,
+            hash_version: scan::CONTENT_HASH_VERSION,
+            generated_files_changed: Vec::new(),
+            generated_lines_written: 0,
+            test_lines_written: 0,
+            files_skipped: Vec::new(),
+            small_files_omitted: Vec::new(),
+            active_time_seconds: 0,
+            first_activity: None,
+            last_activity: None,
+            breaks: Vec::new(),
+            idle_threshold_secs: IDLE_THRESHOLD_SECS,
+            paused_at: None,
+            paused_secs: 0,
+            activity_samples: Vec::new(),
+            files_touched: BTreeSet::new(),
+            mood: None,
+            energy: None,
+            goal_lines: None,
+            goal_minutes: None,
+            command_metric: None,
+            tokei: None,
+            machine: None,
         })
     }
 
-    pub fn save(&self) -> Result<()> {
-        let session_file = PathBuf::from(".ego_session.json");
-        let session_json = serde_json::to_string(self)?;
-        fs::write(session_file, session_json)?;
+    pub fn save(&self) -> Result<(), EgoError> {
+        let session_json =
+            serde_json::to_string(self).map_err(|err| EgoError::io(&self.file, err.into()))?;
+        let saving = self.file.with_file_name(SAVING_SESSION_FILE);
+        retry_transient(|| fs::write(&saving, &session_json))
+            .map_err(|err| EgoError::io(&saving, err))?;
+        retry_transient(|| fs::rename(&saving, &self.file))
+            .map_err(|err| EgoError::io(&self.file, err))?;
+        self.file_modified.set(self.file_mtime());
         Ok(())
     }
 
-    pub fn load() -> Result<Option<Self>> {
-        let session_file = PathBuf::from(".ego_session.json");
-        if session_file.exists() {
-            let session_json = fs::read_to_string(session_file)?;
-            let session = serde_json::from_str(&session_json)?;
-            Ok(Some(session))
-        } else {
-            Ok(None)
+    fn file_mtime(&self) -> Option<SystemTime> {
+        fs::metadata(&self.file)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    /// Picks up a pause or resume saved by another ego process, as when
+    /// `ego pause` runs while `ego start --track-activity` holds the session
+    /// in memory. The file is only read again once it has changed, and a
+    /// file caught halfway through being written is read again next time.
+    pub fn sync_pause(&mut self) {
+        #[derive(Deserialize)]
+        struct PauseState {
+            #[serde(default)]
+            paused_at: Option<DateTime<Local>>,
+            #[serde(default)]
+            paused_secs: i64,
+        }
+
+        let modified = self.file_mtime();
+        if modified.is_none() || modified == self.file_modified.get() {
+            return;
+        }
+        let Some(state) = fs::read_to_string(&self.file)
+            .ok()
+            .and_then(|json| serde_json::from_str::<PauseState>(&json).ok())
+        else {
+            return;
+        };
+        if self.paused_at.is_some() && state.paused_at.is_none() {
+            self.last_activity = None;
         }
+        self.paused_at = state.paused_at;
+        self.paused_secs = state.paused_secs;
+        self.file_modified.set(modified);
     }
 
-    pub fn end(&mut self) -> Result<()> {
-        let final_count = Self::count_all_lines(&self.project_directory)?;
+    /// The file of the session for the project at `dir`, or for the closest
+    /// project containing it.
+    pub fn find(dir: &Path) -> Option<PathBuf> {
+        let dir = path::absolute(dir).ok()?;
+        dir.ancestors()
+            .map(|ancestor| ancestor.join(SESSION_FILE))
+            .find(|file| file.is_file())
+    }
+
+    /// Loads the session for the project at `dir`, or for the closest
+    /// project containing it. See `load_file`. A `dir` that no longer exists
+    /// took its session file with it, so it's reported rather than matched
+    /// against a session further up.
+    pub fn load(dir: &Path) -> Result<Option<Self>, EgoError> {
+        if !dir.is_dir() {
+            return Err(EgoError::DirectoryMissing(dir.to_path_buf()));
+        }
+        Self::find(dir)
+            .map(|file| Self::load_file(&file))
+            .transpose()
+    }
+
+    /// Loads the session saved in `file`. Its paths are relative to the
+    /// directory it was started from, so they are resolved against that
+    /// directory rather than the current one.
+    pub fn load_file(file: &Path) -> Result<Self, EgoError> {
+        let (session, warning) = Self::read_file(file)?;
+        if let Some(warning) = warning {
+            eprintln!("Warning: {}", warning);
+        }
+        Ok(session)
+    }
+
+    /// Like `load_file`, but returns the warning about capped active time
+    /// instead of printing it, for callers that own the terminal.
+    pub fn read_file(file: &Path) -> Result<(Self, Option<String>), EgoError> {
+        let file = &path::absolute(file).map_err(|err| EgoError::io(file, err))?;
+        let session_json = fs::read_to_string(file).map_err(|err| EgoError::io(file, err))?;
+        let mut session: Session =
+            serde_json::from_str(&session_json).map_err(|source| EgoError::CorruptSession {
+                path: file.to_path_buf(),
+                source,
+            })?;
+        session.file = file.to_path_buf();
+        session.file_modified.set(session.file_mtime());
+        if session.hash_version != scan::CONTENT_HASH_VERSION {
+            session.forget_hashes();
+        }
+        if session.id.is_empty() {
+            session.id = session_id(&session.start_time);
+        }
+        // Older sessions don't record where they started, and were saved
+        // there.
+        if let Some(base) = session
+            .working_directory
+            .clone()
+            .or_else(|| file.parent().map(Path::to_path_buf))
+        {
+            session.resolve_paths(&base);
+        }
+        let warning = session.clamp_active_time(Local::now());
+        Ok((session, warning))
+    }
+
+    /// Joins every stored path onto `base`, the directory the session's
+    /// relative paths were recorded in. Absolute paths stay as they are, and
+    /// `.` components are dropped, so a project started as `.` is its
+    /// directory. Subproject paths are relative to the project and are left
+    /// alone.
+    fn resolve_paths(&mut self, base: &Path) {
+        let resolve = |path: &mut PathBuf| *path = base.join(&*path).components().collect();
+        let resolve_all = |paths: &mut Vec<PathBuf>| paths.iter_mut().for_each(resolve);
+        let resolve_renames = |renames: &mut Vec<Rename>| {
+            for rename in renames {
+                resolve(&mut rename.from);
+                resolve(&mut rename.to);
+            }
+        };
+
+        resolve(&mut self.project_directory);
+        self.files = mem::take(&mut self.files)
+            .into_iter()
+            .map(|(path, snapshot)| (base.join(path), snapshot))
+            .collect();
+        self.untracked_files = mem::take(&mut self.untracked_files)
+            .into_iter()
+            .map(|(path, hash)| (base.join(path), hash))
+            .collect();
+        self.files_touched = mem::take(&mut self.files_touched)
+            .into_iter()
+            .map(|path| base.join(path))
+            .collect();
+        resolve_all(&mut self.files_created);
+        resolve_all(&mut self.files_modified);
+        resolve_all(&mut self.files_deleted);
+        resolve_all(&mut self.generated_files_changed);
+        resolve_all(&mut self.files_skipped);
+        resolve_all(&mut self.small_files_omitted);
+        resolve_renames(&mut self.files_renamed);
+        resolve_renames(&mut self.files_moved_out);
+        resolve_renames(&mut self.files_moved_in);
+        for change in &mut self.file_changes {
+            resolve(&mut change.path);
+            if let Some(from) = &mut change.renamed_from {
+                resolve(from);
+            }
+        }
+    }
+
+    /// Drops content hashes made with another hash algorithm. Modified files
+    /// are then told by their modification time, and moves from untracked
+    /// paths aren't followed.
+    fn forget_hashes(&mut self) {
+        for snapshot in self.files.values_mut() {
+            snapshot.hash = None;
+        }
+        self.untracked_files.clear();
+        self.hash_version = scan::CONTENT_HASH_VERSION;
+    }
+
+    /// Finalizes the session and removes the working session file. With
+    /// `keep`, the finalized session is first written to
+    /// `.ego_session.done.json` for other tools to consume.
+    pub fn end(&mut self, options: &EndOptions) -> Result<()> {
+        let generated = self.options.generated_matcher()?;
+        let scan = self.scan()?;
+        let current_files = scan.files;
+        self.files_skipped = scan.skipped;
+        let final_metrics: BTreeMap<&Path, ContentMetrics> = current_files
+            .iter()
+            .map(|(path, current)| {
+                let metrics = match self.files.get(path) {
+                    // Snapshots from before metrics were recorded only have
+                    // a line count.
+                    Some(snapshot)
+                        if snapshot.unchanged_on_disk(current)
+                            && snapshot.metrics.lines == snapshot.lines =>
+                    {
+                        snapshot.metrics
+                    }
+                    _ => self.options.measure(path).0,
+                };
+                (path.as_path(), metrics)
+            })
+            .collect();
+        let final_lines: BTreeMap<&Path, i32> = final_metrics
+            .iter()
+            .map(|(path, metrics)| (*path, metrics.lines))
+            .collect();
+        self.final_metrics = Some(
+            final_metrics
+                .iter()
+                .filter(|(path, _)| !Self::matches(&generated, &self.project_directory, path))
+                .map(|(_, metrics)| *metrics)
+                .sum(),
+        );
+
+        let tests = self.options.test_matcher()?;
+        let mut final_count = 0;
+        let mut generated_delta = 0;
+        let mut test_delta = 0;
+        for (path, lines) in &final_lines {
+            if Self::matches(&generated, &self.project_directory, path) {
+                generated_delta += lines;
+            } else {
+                final_count += lines;
+                if Self::matches(&tests, &self.project_directory, path) {
+                    test_delta += lines;
+                }
+            }
+        }
+        for (path, snapshot) in &self.files {
+            if Self::matches(&generated, &self.project_directory, path) {
+                generated_delta -= snapshot.lines;
+            } else if Self::matches(&tests, &self.project_directory, path) {
+                test_delta -= snapshot.lines;
+            }
+        }
         self.final_line_count = Some(final_count);
 
         self.lines_written = Some(final_count - self.initial_line_count);
+        if self.options.prose_accurate {
+            self.final_prose_words = Some(
+                current_files
+                    .keys()
+                    .filter(|path| !Self::matches(&generated, &self.project_directory, path))
+                    .filter_map(|path| self.options.prose_words(path))
+                    .sum(),
+            );
+        }
+        self.generated_lines_written = generated_delta;
+
+        let mut created: Vec<PathBuf> = current_files
+            .keys()
+            .filter(|path| !self.files.contains_key(*path))
+            .cloned()
+            .collect();
+        let mut modified: Vec<PathBuf> = current_files
+            .iter()
+            .filter(|(path, current)| {
+                self.files.get(*path).is_some_and(|snapshot| {
+                    options
+                        .modified_detection
+                        .is_modified(path, snapshot, current)
+                })
+            })
+            .map(|(path, _)| path.clone())
+            .collect();
+        let mut deleted: Vec<PathBuf> = self
+            .files
+            .keys()
+            .filter(|path| !current_files.contains_key(*path))
+            .cloned()
+            .collect();
+
+        self.generated_files_changed.clear();
+        for list in [&mut created, &mut modified, &mut deleted] {
+            let (generated_paths, tracked): (Vec<_>, Vec<_>) = list
+                .drain(..)
+                .partition(|path| Self::matches(&generated, &self.project_directory, path));
+            self.generated_files_changed.extend(generated_paths);
+            *list = tracked;
+        }
+        self.generated_files_changed.sort();
+
+        self.files_renamed = self.detect_renames(
+            &mut created,
+            &mut deleted,
+            &final_lines,
+            options.rename_threshold,
+        );
+        if self.options.follow_moves {
+            (self.files_moved_out, self.files_moved_in) =
+                self.detect_moves(&mut created, &mut deleted, &current_files)?;
+            // A move across the ignore boundary neither adds nor removes
+            // lines.
+            let mut moved_out = 0;
+            for moved in &self.files_moved_out {
+                let lines = self
+                    .files
+                    .get(&moved.from)
+                    .map_or(0, |snapshot| snapshot.lines);
+                moved_out += lines;
+                if Self::matches(&tests, &self.project_directory, &moved.from) {
+                    test_delta += lines;
+                }
+            }
+            let mut moved_in = 0;
+            for moved in &self.files_moved_in {
+                let lines = final_lines.get(moved.to.as_path()).copied().unwrap_or(0);
+                moved_in += lines;
+                if Self::matches(&tests, &self.project_directory, &moved.to) {
+                    test_delta -= lines;
+                }
+            }
+            self.lines_written = Some(final_count - self.initial_line_count + moved_out - moved_in);
+        }
+
+        self.test_lines_written = test_delta;
+
+        self.small_files_omitted.clear();
+        for list in [&mut created, &mut modified, &mut deleted] {
+            let (small, tracked): (Vec<_>, Vec<_>) = list.drain(..).partition(|path| {
+                let initial = self.files.get(path).map_or(0, |snapshot| snapshot.lines);
+                let last = final_lines.get(path.as_path()).copied().unwrap_or(0);
+                initial.max(last) < options.min_file_lines
+            });
+            self.small_files_omitted.extend(small);
+            *list = tracked;
+        }
+        self.small_files_omitted.sort();
+        self.file_changes = Vec::new();
+        for (kind, paths) in [
+            (ChangeKind::Created, &created),
+            (ChangeKind::Modified, &modified),
+            (ChangeKind::Deleted, &deleted),
+        ] {
+            for path in paths {
+                let final_lines = final_lines.get(path.as_path()).copied().unwrap_or(0);
+                let (lines_added, lines_removed) =
+                    self.line_diff(self.files.get(path), path, final_lines);
+                self.file_changes.push(FileChange {
+                    path: path.clone(),
+                    kind,
+                    initial_lines: self.files.get(path).map_or(0, |snapshot| snapshot.lines),
+                    final_lines,
+                    initial_chars: self
+                        .files
+                        .get(path)
+                        .map_or(0, |snapshot| snapshot.metrics.chars),
+                    final_chars: final_metrics
+                        .get(path.as_path())
+                        .map_or(0, |metrics| metrics.chars),
+                    lines_added,
+                    lines_removed,
+                    renamed_from: None,
+                });
+            }
+        }
+        for rename in &self.files_renamed {
+            let final_lines = final_lines.get(rename.to.as_path()).copied().unwrap_or(0);
+            let (lines_added, lines_removed) =
+                self.line_diff(self.files.get(&rename.from), &rename.to, final_lines);
+            self.file_changes.push(FileChange {
+                path: rename.to.clone(),
+                kind: ChangeKind::Renamed,
+                initial_lines: self
+                    .files
+                    .get(&rename.from)
+                    .map_or(0, |snapshot| snapshot.lines),
+                final_lines,
+                initial_chars: self
+                    .files
+                    .get(&rename.from)
+                    .map_or(0, |snapshot| snapshot.metrics.chars),
+                final_chars: final_metrics
+                    .get(rename.to.as_path())
+                    .map_or(0, |metrics| metrics.chars),
+                lines_added,
+                lines_removed,
+                renamed_from: Some(rename.from.clone()),
+            });
+        }
+        self.files_created = created;
+        self.files_modified = modified;
+        self.files_deleted = deleted;
+
+        // Without activity tracking, the earliest change to a file is the
+        // best guess at when work started.
+        if self.first_activity.is_none() {
+            self.first_activity = self
+                .files_created
+                .iter()
+                .chain(&self.files_modified)
+                .filter_map(|path| current_files.get(path))
+                .map(|current| DateTime::<Local>::from(current.modified))
+                .filter(|modified| *modified >= self.start_time)
+                .min();
+        }
+
+        self.subprojects = self.subproject_changes(&generated, &final_lines)?;
+        if self.options.analyze_terms {
+            let mut counts = BTreeMap::new();
+            for change in &self.file_changes {
+                if change.kind == ChangeKind::Deleted {
+                    continue;
+                }
+                let baseline = self
+                    .files
+                    .get(change.renamed_from.as_ref().unwrap_or(&change.path))
+                    .map_or(&[][..], |snapshot| &snapshot.line_hashes);
+                terms::count_added(&change.path, baseline, &mut counts);
+            }
+            self.top_terms = terms::top(counts);
+        }
 
-        fs::remove_file(".ego_session.json")?;
+        if options.dry_run {
+            return Ok(());
+        }
+        if options.keep {
+            fs::write(
+                self.file.with_file_name(FINISHED_SESSION_FILE),
+                serde_json::to_string(self)?,
+            )?;
+        }
+        fs::remove_file(&self.file)?;
         Ok(())
     }
 
-    fn count_all_lines(dir: &Path) -> Result<i32> {
-        fn visit_dirs(dir: &Path, acc: &mut i32) -> io::Result<()> {
-            if dir.file_name().map_or(false, |name| {
-                let name_str = name.to_string_lossy();
-                name_str.starts_with(".")
-            }) {
-                return Ok(());
-            }
-
-            for entry in fs::read_dir(dir)? {
-                let entry = entry?;
-                let path = entry.path();
-
-                if path.is_dir() {
-                    visit_dirs(&path, acc)?;
-                } else if path.is_file() {
-                    let extension = path.extension().and_then(|e| e.to_str());
-                    if let Some(ext) = extension {
-                        if [
-                            "rs", "txt", "md", "py", "js", "html", "css", "c", "cpp", "h", "hpp",
-                            "java", "json", "yaml", "yml", "toml",
-                        ]
-                        .contains(&ext.to_lowercase().as_str())
+    /// A warning when the session file isn't in the project directory the
+    /// session tracks, as happens when a project is copied or moved along
+    /// with its session file. Ending the session still scans the recorded
+    /// directory, not the one the file was found in.
+    pub fn directory_mismatch(&self) -> Option<String> {
+        let project = fs::canonicalize(&self.project_directory).ok()?;
+        let found_in = fs::canonicalize(self.file.parent()?).ok()?;
+        (project != found_in).then(|| {
+            format!(
+                "the session file in {} tracks {}, so that is the directory it ends against",
+                found_in.display(),
+                project.display()
+            )
+        })
+    }
+
+    /// Removes the working session file without finalizing the session or
+    /// recording it in the history.
+    pub fn discard(&self) -> Result<(), EgoError> {
+        fs::remove_file(&self.file).map_err(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => EgoError::NoSession,
+            _ => EgoError::io(&self.file, err),
+        })
+    }
+
+    /// Whether the session file is still there, which it isn't once the
+    /// session has been ended.
+    pub fn is_active(&self) -> bool {
+        self.file.exists()
+    }
+
+    /// Caps `active_time_seconds` at the time elapsed since the session
+    /// started, so a corrupted value can't produce nonsensical rates. Returns
+    /// a warning when it had to.
+    pub fn clamp_active_time(&mut self, now: DateTime<Local>) -> Option<String> {
+        let span = (now - self.start_time).num_seconds().max(0);
+        if (0..=span).contains(&self.active_time_seconds) {
+            return None;
+        }
+        let warning = format!(
+            "session {} recorded {}s of active time in {}s; capping it",
+            self.id, self.active_time_seconds, span
+        );
+        self.active_time_seconds = self.active_time_seconds.clamp(0, span);
+        Some(warning)
+    }
+
+    /// The last moment the session is known to have been in use: its latest
+    /// recorded activity, or when its file was last saved, if later.
+    pub fn last_seen(&self) -> DateTime<Local> {
+        let saved = fs::metadata(&self.file)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .map(DateTime::<Local>::from);
+        [Some(self.start_time), self.last_activity, saved]
+            .into_iter()
+            .flatten()
+            .max()
+            .unwrap_or(self.start_time)
+    }
+
+    /// Whether the session has gone unused for more than `timeout_secs`, as
+    /// when `ego end` was forgotten.
+    pub fn is_stale(&self, now: DateTime<Local>, timeout_secs: i64) -> bool {
+        (now - self.last_seen()).num_seconds() > timeout_secs
+    }
+
+    /// Adds the time since the previous activity to `active_time_seconds`,
+    /// unless the gap was long enough to count as a break, in which case the
+    /// gap is recorded in `breaks`.
+    pub fn record_activity(&mut self, now: DateTime<Local>) {
+        if self.paused_at.is_some() {
+            return;
+        }
+        if let Some(last_activity) = self.last_activity {
+            let elapsed = (now - last_activity).num_seconds();
+            if (0..=self.idle_threshold_secs).contains(&elapsed) {
+                self.active_time_seconds += elapsed;
+            } else if elapsed > self.idle_threshold_secs {
+                self.breaks.push(Break {
+                    started_at: last_activity,
+                    duration_secs: elapsed,
+                });
+            }
+        }
+        self.first_activity.get_or_insert(now);
+        self.last_activity = Some(now);
+        self.record_sample(now);
+    }
+
+    /// Starts a new stretch of activity without crediting the time since the
+    /// previous one, which is recorded as a break instead. Used when the
+    /// system reported the user as away in between.
+    pub fn resume_activity(&mut self, now: DateTime<Local>) {
+        if self.paused_at.is_some() {
+            return;
+        }
+        if let Some(last_activity) = self.last_activity {
+            self.breaks.push(Break {
+                started_at: last_activity,
+                duration_secs: (now - last_activity).num_seconds().max(0),
+            });
+        }
+        self.first_activity.get_or_insert(now);
+        self.last_activity = Some(now);
+        self.record_sample(now);
+    }
+
+    /// Pauses the session, unless it already is. Returns whether it was
+    /// paused now.
+    pub fn pause(&mut self, now: DateTime<Local>) -> bool {
+        if self.paused_at.is_some() {
+            return false;
+        }
+        self.paused_at = Some(now);
+        true
+    }
+
+    /// Ends a pause, returning how long it lasted, or `None` if the session
+    /// wasn't paused. The next activity starts a new stretch rather than
+    /// continuing the one before the pause.
+    pub fn resume(&mut self, now: DateTime<Local>) -> Option<i64> {
+        let paused_at = self.paused_at.take()?;
+        let duration = (now - paused_at).num_seconds().max(0);
+        self.paused_secs += duration;
+        self.last_activity = None;
+        Some(duration)
+    }
+
+    /// Total time spent paused up to `now`, including a pause still going on.
+    pub fn paused_secs_at(&self, now: DateTime<Local>) -> i64 {
+        let current = self
+            .paused_at
+            .map_or(0, |paused_at| (now - paused_at).num_seconds().max(0));
+        self.paused_secs + current
+    }
+
+    /// Keeps at most one sample per minute, and at most two days' worth of
+    /// samples, so long sessions don't bloat the session file.
+    fn record_sample(&mut self, now: DateTime<Local>) {
+        let same_minute = self
+            .activity_samples
+            .last()
+            .is_some_and(|last| (now - *last).num_seconds() < 60);
+        if !same_minute && self.activity_samples.len() < MAX_ACTIVITY_SAMPLES {
+            self.activity_samples.push(now);
+        }
+    }
+
+    /// Number of files per detected encoding in the start snapshot. Empty
+    /// unless the session was started with encoding detection.
+    pub fn encoding_breakdown(&self) -> BTreeMap<&str, usize> {
+        let mut breakdown = BTreeMap::new();
+        for snapshot in self.files.values() {
+            if let Some(encoding) = &snapshot.encoding {
+                *breakdown.entry(encoding.as_str()).or_insert(0) += 1;
+            }
+        }
+        breakdown
+    }
+
+    /// The project's current totals and the files changed since the session
+    /// started, from one scan and without ending the session. Unless
+    /// `rescan` is set, files still matching their start snapshot on disk
+    /// are taken as unchanged and reuse its counts instead of being read
+    /// again. Generated files are left out.
+    pub fn pending(&self, rescan: bool) -> Result<(ContentMetrics, Vec<FileChange>)> {
+        let generated = self.options.generated_matcher()?;
+        let current_files = self.scan()?.files;
+        let is_generated = |path: &Path| Self::matches(&generated, &self.project_directory, path);
+
+        let mut total = ContentMetrics::default();
+        let mut changes = Vec::new();
+        for (path, current) in &current_files {
+            if is_generated(path) {
+                continue;
+            }
+            let snapshot = self.files.get(path);
+            let kind = match snapshot {
+                // Snapshots from before metrics were recorded only have a
+                // line count.
+                Some(snapshot)
+                    if !rescan
+                        && snapshot.unchanged_on_disk(current)
+                        && snapshot.metrics.lines == snapshot.lines =>
+                {
+                    total = total + snapshot.metrics;
+                    continue;
+                }
+                None => Some(ChangeKind::Created),
+                Some(snapshot) => {
+                    let modified = match snapshot.hash {
+                        Some(hash) => scan::content_hash(path) != Some(hash),
+                        None => snapshot.modified != current.modified,
+                    };
+                    modified.then_some(ChangeKind::Modified)
+                }
+            };
+            let metrics = self.options.measure(path).0;
+            total = total + metrics;
+            let Some(kind) = kind else {
+                continue;
+            };
+            let metrics = self.options.measure(path).0;
+            let (lines_added, lines_removed) = self.line_diff(snapshot, path, metrics.lines);
+            changes.push(FileChange {
+                path: path.clone(),
+                kind,
+                initial_lines: snapshot.map_or(0, |snapshot| snapshot.lines),
+                final_lines: metrics.lines,
+                initial_chars: snapshot.map_or(0, |snapshot| snapshot.metrics.chars),
+                final_chars: metrics.chars,
+                lines_added,
+                lines_removed,
+                renamed_from: None,
+            });
+        }
+        for (path, snapshot) in &self.files {
+            if !current_files.contains_key(path) && !is_generated(path) {
+                changes.push(FileChange {
+                    path: path.clone(),
+                    kind: ChangeKind::Deleted,
+                    initial_lines: snapshot.lines,
+                    final_lines: 0,
+                    initial_chars: snapshot.metrics.chars,
+                    final_chars: 0,
+                    lines_added: 0,
+                    lines_removed: snapshot.lines,
+                    renamed_from: None,
+                });
+            }
+        }
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok((total, changes))
+    }
+
+    /// Lines added to and removed from a file since `snapshot` was taken,
+    /// diffing the snapshot's lines against `path` as it is now. A file
+    /// without a snapshot was created, and a snapshot from before line
+    /// sequences were kept only gives the net change.
+    fn line_diff(
+        &self,
+        snapshot: Option<&FileSnapshot>,
+        path: &Path,
+        final_lines: i32,
+    ) -> (i32, i32) {
+        match snapshot {
+            None => (final_lines, 0),
+            Some(snapshot) if snapshot.line_sequence.is_empty() && snapshot.lines > 0 => {
+                let delta = final_lines - snapshot.lines;
+                (delta.max(0), (-delta).max(0))
+            }
+            Some(snapshot) => {
+                scan::diff_line_counts(&snapshot.line_sequence, &self.options.line_sequence(path))
+            }
+        }
+    }
+
+    /// Splits the session's changes by subproject. Each file belongs to the
+    /// innermost subproject containing it; files outside every subproject
+    /// only count towards the overall totals.
+    fn subproject_changes(
+        &self,
+        generated: &GlobSet,
+        final_lines: &BTreeMap<&Path, i32>,
+    ) -> Result<Vec<SubprojectChanges>> {
+        let mut subprojects: Vec<SubprojectChanges> =
+            scan::subproject_roots(&self.project_directory, &self.options)?
+                .into_iter()
+                .map(|path| SubprojectChanges {
+                    path,
+                    initial_line_count: 0,
+                    final_line_count: 0,
+                    files_changed: 0,
+                })
+                .collect();
+        if subprojects.is_empty() {
+            return Ok(subprojects);
+        }
+
+        let find = |path: &Path| {
+            let relative = path.strip_prefix(&self.project_directory).unwrap_or(path);
+            subprojects
+                .iter()
+                .enumerate()
+                .filter(|(_, subproject)| relative.starts_with(&subproject.path))
+                .max_by_key(|(_, subproject)| subproject.path.components().count())
+                .map(|(index, _)| index)
+        };
+        let initial: Vec<_> = self
+            .files
+            .iter()
+            .filter(|(path, _)| !Self::matches(generated, &self.project_directory, path))
+            .filter_map(|(path, snapshot)| find(path).map(|index| (index, snapshot.lines)))
+            .collect();
+        let last: Vec<_> = final_lines
+            .iter()
+            .filter(|(path, _)| !Self::matches(generated, &self.project_directory, path))
+            .filter_map(|(path, lines)| find(path).map(|index| (index, *lines)))
+            .collect();
+        let changed: Vec<_> = self
+            .file_changes
+            .iter()
+            .filter_map(|change| find(&change.path))
+            .collect();
+
+        for (index, lines) in initial {
+            subprojects[index].initial_line_count += lines;
+        }
+        for (index, lines) in last {
+            subprojects[index].final_line_count += lines;
+        }
+        for index in changed {
+            subprojects[index].files_changed += 1;
+        }
+        Ok(subprojects)
+    }
+
+    /// Pairs deleted files with created ones whose contents are at least
+    /// `threshold` similar, most similar pairs first, and removes the pairs
+    /// from both lists. Files with identical contents are always paired.
+    fn detect_renames(
+        &self,
+        created: &mut Vec<PathBuf>,
+        deleted: &mut Vec<PathBuf>,
+        final_lines: &BTreeMap<&Path, i32>,
+        threshold: f64,
+    ) -> Vec<Rename> {
+        if created.is_empty() || deleted.is_empty() {
+            return Vec::new();
+        }
+
+        let created_files: Vec<(Option<u128>, Vec<u64>)> = created
+            .iter()
+            .map(|path| (scan::content_hash(path), scan::fingerprint(path)))
+            .collect();
+        let mut candidates = Vec::new();
+        for (d, from) in deleted.iter().enumerate() {
+            let Some(snapshot) = self.files.get(from) else {
+                continue;
+            };
+            for (c, (hash, fingerprint)) in created_files.iter().enumerate() {
+                let similarity = if snapshot.hash.is_some() && snapshot.hash == *hash {
+                    1.0
+                } else {
+                    scan::similarity(&snapshot.fingerprint, fingerprint)
+                };
+                if similarity >= threshold {
+                    candidates.push((similarity, d, c));
+                }
+            }
+        }
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let mut renames = Vec::new();
+        let mut paired_deleted = vec![false; deleted.len()];
+        let mut paired_created = vec![false; created.len()];
+        for (similarity, d, c) in candidates {
+            if paired_deleted[d] || paired_created[c] {
+                continue;
+            }
+            paired_deleted[d] = true;
+            paired_created[c] = true;
+
+            let initial = self
+                .files
+                .get(&deleted[d])
+                .map_or(0, |snapshot| snapshot.lines);
+            let last = final_lines.get(created[c].as_path()).copied().unwrap_or(0);
+            renames.push(Rename {
+                from: deleted[d].clone(),
+                to: created[c].clone(),
+                similarity,
+                churn: ((1.0 - similarity) * f64::from(initial.max(last))).round() as i32,
+            });
+        }
+
+        let mut index = 0;
+        deleted.retain(|_| {
+            index += 1;
+            !paired_deleted[index - 1]
+        });
+        let mut index = 0;
+        created.retain(|_| {
+            index += 1;
+            !paired_created[index - 1]
+        });
+        renames.sort_by(|a, b| a.to.cmp(&b.to));
+        renames
+    }
+
+    /// Pairs deleted files with untracked files that appeared during the
+    /// session, and created files with untracked files that went away, by
+    /// exact content hash. Paired paths are removed from `created` and
+    /// `deleted` and returned as moves out of and into scope.
+    fn detect_moves(
+        &self,
+        created: &mut Vec<PathBuf>,
+        deleted: &mut Vec<PathBuf>,
+        current_files: &BTreeMap<PathBuf, ScannedFile>,
+    ) -> Result<(Vec<Rename>, Vec<Rename>)> {
+        let untracked = if self.project_directory.is_dir() {
+            scan::untracked_hashes(
+                &self.project_directory,
+                current_files,
+                &self.options,
+                &ScanDeadline::NONE,
+            )?
+        } else {
+            BTreeMap::new()
+        };
+
+        let mut arrived: Vec<(&PathBuf, u128)> = untracked
+            .iter()
+            .filter(|(path, hash)| self.untracked_files.get(*path) != Some(*hash))
+            .map(|(path, hash)| (path, *hash))
+            .collect();
+        let mut moved_out = Vec::new();
+        deleted.retain(|from| {
+            let Some(hash) = self.files.get(from).and_then(|snapshot| snapshot.hash) else {
+                return true;
+            };
+            let Some(index) = arrived.iter().position(|(_, other)| *other == hash) else {
+                return true;
+            };
+            let (to, _) = arrived.remove(index);
+            moved_out.push(Rename {
+                from: from.clone(),
+                to: to.clone(),
+                similarity: 1.0,
+                churn: 0,
+            });
+            false
+        });
+
+        let mut departed: Vec<(&PathBuf, u128)> = self
+            .untracked_files
+            .iter()
+            .filter(|(path, hash)| untracked.get(*path) != Some(*hash))
+            .map(|(path, hash)| (path, *hash))
+            .collect();
+        let mut moved_in = Vec::new();
+        created.retain(|to| {
+            let Some(hash) = scan::content_hash(to) else {
+                return true;
+            };
+            let Some(index) = departed.iter().position(|(_, other)| *other == hash) else {
+                return true;
+            };
+            let (from, _) = departed.remove(index);
+            moved_in.push(Rename {
+                from: from.clone(),
+                to: to.clone(),
+                similarity: 1.0,
+                churn: 0,
+            });
+            false
+        });
+
+        Ok((moved_out, moved_in))
+    }
+
+    /// Scans the project directory, treating a directory that has since been
+    /// moved away with its session file (see `directory_mismatch`) as empty
+    /// so that the session can still be finalized.
+    fn scan(&self) -> Result<Scan> {
+        if self.project_directory.is_dir() {
+            scan::scan_files(&self.project_directory, &self.options)
+        } else {
+            Ok(Scan::default())
+        }
+    }
+
+    fn matches(globs: &GlobSet, project_directory: &Path, path: &Path) -> bool {
+        !globs.is_empty() && globs.is_match(path.strip_prefix(project_directory).unwrap_or(path))
+    }
+
+    /// Snapshots the scanned files on several threads, since reading and
+    /// hashing every file is what takes the time. Each thread takes the next
+    /// file not yet claimed, so a directory of large files doesn't leave one
+    /// thread with all the work. The results are merged into a sorted map, so
+    /// they don't depend on which thread finishes first.
+    fn snapshot_files(
+        survey: Scan,
+        options: &ScanOptions,
+        deadline: &ScanDeadline,
+    ) -> Result<BTreeMap<PathBuf, FileSnapshot>> {
+        let files: Vec<(PathBuf, ScannedFile)> = survey.files.into_iter().collect();
+        let threads = thread::available_parallelism()
+            .map_or(1, |threads| threads.get())
+            .min(files.len())
+            .max(1);
+        let next = AtomicUsize::new(0);
+
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut snapshots = Vec::new();
+                        while let Some((path, current)) =
+                            files.get(next.fetch_add(1, Ordering::Relaxed))
                         {
-                            if let Ok(file) = fs::File::open(&path) {
-                                let reader = io::BufReader::new(file);
-                                *acc += reader.lines().count() as i32;
-                            }
+                            deadline.check()?;
+                            snapshots
+                                .push((path.clone(), Self::snapshot_file(options, path, current)));
                         }
-                    }
-                }
+                        Ok::<_, anyhow::Error>(snapshots)
+                    })
+                })
+                .collect();
+
+            let mut snapshots = BTreeMap::new();
+            for worker in workers {
+                let chunk = worker
+                    .join()
+                    .map_err(|_| anyhow::anyhow!("a scan thread panicked"))??;
+                snapshots.extend(chunk);
+            }
+            Ok(snapshots)
+        })
+    }
+
+    /// Snapshots one file from a single read of its contents.
+    fn snapshot_file(options: &ScanOptions, path: &Path, current: &ScannedFile) -> FileSnapshot {
+        let contents = options.inspect(path);
+        FileSnapshot {
+            lines: contents.metrics.lines,
+            modified: current.modified,
+            size: Some(current.size),
+            hash: contents.hash,
+            encoding: contents.encoding.map(String::from),
+            fingerprint: contents.fingerprint,
+            prose_words: contents.prose_words,
+            line_hashes: contents.line_hashes,
+            line_sequence: contents.line_sequence,
+            metrics: contents.metrics,
+        }
+    }
+}
+
+/// Runs `operation`, retrying with exponential backoff while it fails with
+/// an error that may clear up by itself, as network file systems produce
+/// when the server is briefly unreachable. Other errors are returned at once.
+fn retry_transient<T>(mut operation: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let mut delay = SAVE_BACKOFF;
+    for _ in 1..SAVE_ATTEMPTS {
+        match operation() {
+            Err(err) if is_transient(&err) => {
+                thread::sleep(delay);
+                delay *= 2;
             }
-            Ok(())
+            result => return result,
         }
+    }
+    operation()
+}
+
+fn is_transient(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::ResourceBusy
+            | io::ErrorKind::StaleNetworkFileHandle
+    )
+}
+
+pub fn default_project_name(project_directory: &Path) -> String {
+    let path = fs::canonicalize(project_directory).unwrap_or_else(|_| project_directory.into());
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+/// A session's ID, derived from its start time so that sessions recorded
+/// before IDs existed get the same one whenever it is computed.
+pub fn session_id(start_time: &DateTime<Local>) -> String {
+    format!(
+        "{}-{:04x}",
+        start_time.format("%Y%m%d-%H%M%S"),
+        start_time.timestamp_subsec_nanos() % 0x10000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::SessionStats;
+    use crate::test_util::TempDir;
+    use chrono::TimeDelta;
+
+    fn start_in(project: &str, options: ScanOptions) -> Session {
+        let survey = Session::survey(project, &options, &ScanDeadline::NONE).unwrap();
+        Session::new(project, None, options, survey, &ScanDeadline::NONE).unwrap()
+    }
+
+    fn start(dir: &TempDir) -> Session {
+        start_in(dir.path().to_str().unwrap(), ScanOptions::default())
+    }
+
+    fn end_options(modified_detection: ModifiedDetection) -> EndOptions {
+        EndOptions {
+            keep: false,
+            dry_run: true,
+            modified_detection,
+            min_file_lines: 0,
+            rename_threshold: 0.5,
+        }
+    }
+
+    #[test]
+    fn reloaded_session_detects_modified_file() {
+        let dir = TempDir::new();
+        let file = dir.write("main.rs", "fn main() {}\n");
+        let session = start(&dir);
+        session.save().unwrap();
+
+        let mut session = Session::load_file(&dir.path().join(SESSION_FILE)).unwrap();
+        fs::write(&file, "fn main() {\n    println!(\"hi\");\n}\n").unwrap();
+        session.end(&end_options(ModifiedDetection::Hash)).unwrap();
+
+        assert_eq!(session.files_modified, vec![file]);
+        assert!(session.files_created.is_empty());
+        assert!(session.files_deleted.is_empty());
+    }
+
+    #[test]
+    fn fast_path_gives_same_changes_as_rehashing() {
+        let dir = TempDir::new();
+        dir.write("unchanged.rs", "fn a() {}\n");
+        let modified = dir.write("modified.rs", "fn b() {}\n");
+        let touched = dir.write("touched.rs", "fn c() {}\n");
+        let deleted = dir.write("deleted.rs", "fn d() {}\n");
+        start(&dir).save().unwrap();
+        let session_file = dir.path().join(SESSION_FILE);
+        let mut fast = Session::load_file(&session_file).unwrap();
+        let mut slow = Session::load_file(&session_file).unwrap();
+        // A modification time no file has makes every snapshot look changed
+        // on disk, so each file is hashed and counted again.
+        for snapshot in slow.files.values_mut() {
+            snapshot.modified = SystemTime::UNIX_EPOCH;
+        }
+
+        fs::write(&modified, "fn b() {\n    todo!()\n}\n").unwrap();
+        fs::write(&touched, "fn c() {}\n").unwrap();
+        fs::remove_file(&deleted).unwrap();
+        let created = dir.write("created.rs", "fn e() {}\n");
+
+        fast.end(&end_options(ModifiedDetection::Hash)).unwrap();
+        slow.end(&end_options(ModifiedDetection::Hash)).unwrap();
+        for session in [&fast, &slow] {
+            assert_eq!(session.files_created, vec![created.clone()]);
+            assert_eq!(session.files_modified, vec![modified.clone()]);
+            assert_eq!(session.files_deleted, vec![deleted.clone()]);
+        }
+        assert_eq!(fast.lines_written, slow.lines_written);
+        assert_eq!(fast.final_line_count, slow.final_line_count);
+    }
+
+    #[test]
+    fn pending_matches_rescan() {
+        let dir = TempDir::new();
+        dir.write("unchanged.rs", "fn a() {}\n");
+        let modified = dir.write("modified.rs", "fn b() {}\n");
+        let touched = dir.write("touched.rs", "fn c() {}\n");
+        start(&dir).save().unwrap();
+        let session = Session::load_file(&dir.path().join(SESSION_FILE)).unwrap();
+
+        fs::write(&modified, "fn b() {\n    todo!()\n}\n").unwrap();
+        fs::write(&touched, "fn c() {}\n").unwrap();
+        let created = dir.write("created.rs", "fn e() {}\n");
+
+        let (cached_total, cached_changes) = session.pending(false).unwrap();
+        let (total, changes) = session.pending(true).unwrap();
+        assert_eq!(cached_total, total);
+        assert_eq!(total.lines, 6);
+        for changes in [&cached_changes, &changes] {
+            let paths: Vec<_> = changes.iter().map(|change| &change.path).collect();
+            assert_eq!(paths, vec![&created, &modified]);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn start_survives_symlink_cycle() {
+        let dir = TempDir::new();
+        let file = dir.write("sub/lib.rs", "fn a() {}\n");
+        std::os::unix::fs::symlink("..", dir.path().join("sub/loop")).unwrap();
+
+        let session = start(&dir);
+        assert!(session.files.contains_key(&file));
+        assert_eq!(session.initial_line_count, 1);
+    }
+
+    #[test]
+    fn session_file_is_never_counted() {
+        let dir = TempDir::new();
+        dir.write("main.rs", "fn main() {}\n");
+        dir.write(FINISHED_SESSION_FILE, "{}");
+        let previous = env::current_dir().unwrap();
+        env::set_current_dir(dir.path()).unwrap();
+
+        // With `.json` tracked, only the explicit exclusion keeps the
+        // session files out, even after the session file changes on save.
+        let options = ScanOptions {
+            extensions: vec!["rs".to_string(), "json".to_string()],
+            ..ScanOptions::default()
+        };
+        let mut session = start_in(".", options);
+        session.save().unwrap();
+        session.record_activity(Local::now());
+        session.save().unwrap();
+        session.end(&end_options(ModifiedDetection::Hash)).unwrap();
+        let stats = SessionStats::new(&session, Local::now());
+        env::set_current_dir(previous).unwrap();
+
+        let is_session_file = |path: &PathBuf| {
+            path.file_name()
+                .is_some_and(|name| name == SESSION_FILE || name == FINISHED_SESSION_FILE)
+        };
+        assert!(!session.files.keys().any(is_session_file));
+        assert!(!session.files_created.iter().any(is_session_file));
+        assert!(!session.files_modified.iter().any(is_session_file));
+        assert!(!session.files_deleted.iter().any(is_session_file));
+        assert!(!session.files_skipped.iter().any(is_session_file));
+        assert!(!session.files_touched.iter().any(is_session_file));
+        assert!(!stats
+            .file_changes
+            .iter()
+            .any(|change| is_session_file(&change.path)));
+        assert_eq!(session.files.len(), 1);
+    }
+
+    /// An unsaved session last seen at `base`, so `last_seen` doesn't
+    /// depend on a session file's modification time.
+    fn last_seen_at(dir: &TempDir, base: DateTime<Local>) -> Session {
+        let mut session = start(dir);
+        session.start_time = base - TimeDelta::hours(1);
+        session.last_activity = Some(base);
+        assert_eq!(session.last_seen(), base);
+        session
+    }
+
+    #[test]
+    fn session_at_timeout_is_not_stale() {
+        let dir = TempDir::new();
+        let base = Local::now() - TimeDelta::days(1);
+        let session = last_seen_at(&dir, base);
+        assert!(!session.is_stale(base + TimeDelta::seconds(3600), 3600));
+    }
+
+    #[test]
+    fn session_past_timeout_is_stale() {
+        let dir = TempDir::new();
+        let base = Local::now() - TimeDelta::days(1);
+        let session = last_seen_at(&dir, base);
+        assert!(session.is_stale(base + TimeDelta::seconds(3601), 3600));
+    }
+
+    #[test]
+    fn session_seen_after_now_is_not_stale() {
+        let dir = TempDir::new();
+        let base = Local::now() - TimeDelta::days(1);
+        let session = last_seen_at(&dir, base);
+        assert!(!session.is_stale(base - TimeDelta::seconds(60), 0));
+    }
+
+    #[test]
+    fn active_time_is_capped_at_span() {
+        let dir = TempDir::new();
+        let mut session = start(&dir);
+        let now = session.start_time + TimeDelta::seconds(600);
+        session.active_time_seconds = 900;
+        assert!(session.clamp_active_time(now).is_some());
+        assert_eq!(session.active_time_seconds, 600);
+        assert!(session.clamp_active_time(now).is_none());
+    }
+
+    #[test]
+    fn session_file_moved_from_its_project_warns() {
+        let project = TempDir::new();
+        project.write("main.rs", "fn main() {}\n");
+        start(&project).save().unwrap();
+        let session_file = project.path().join(SESSION_FILE);
+        let copy = TempDir::new();
+        fs::copy(&session_file, copy.path().join(SESSION_FILE)).unwrap();
+
+        let session = Session::load_file(&session_file).unwrap();
+        assert_eq!(session.directory_mismatch(), None);
+
+        let copied = Session::load_file(&copy.path().join(SESSION_FILE)).unwrap();
+        let warning = copied.directory_mismatch().unwrap();
+        let project = fs::canonicalize(project.path()).unwrap();
+        let copy = fs::canonicalize(copy.path()).unwrap();
+        assert!(warning.contains(&copy.display().to_string()));
+        assert!(warning.contains(&project.display().to_string()));
+    }
+
+    #[test]
+    fn save_replaces_session_file_whole() {
+        let dir = TempDir::new();
+        dir.write("main.rs", "fn main() {}\n");
+        let mut session = start(&dir);
+        session.save().unwrap();
+        session.record_activity(Local::now());
+        session.save().unwrap();
+
+        assert!(!dir.path().join(SAVING_SESSION_FILE).exists());
+        let (loaded, _) = Session::read_file(&dir.path().join(SESSION_FILE)).unwrap();
+        assert_eq!(loaded.last_activity, session.last_activity);
+    }
+
+    #[test]
+    fn paths_resolve_against_start_directory() {
+        let dir = TempDir::new();
+        let file = dir.write("main.rs", "fn main() {}\n");
+        let mut session = start(&dir);
+        session.project_directory = PathBuf::from(".");
+        session.working_directory = Some(dir.path().to_path_buf());
+        session.files = mem::take(&mut session.files)
+            .into_iter()
+            .map(|(path, snapshot)| (path.strip_prefix(dir.path()).unwrap().into(), snapshot))
+            .collect();
+        session.save().unwrap();
+
+        let session = Session::load_file(&dir.path().join(SESSION_FILE)).unwrap();
+        assert_eq!(session.project_directory, dir.path());
+        assert!(session.files.contains_key(&file));
+    }
+
+    #[test]
+    fn deleted_project_is_reported_missing() {
+        let dir = TempDir::new();
+        dir.write("main.rs", "fn main() {}\n");
+        start(&dir).save().unwrap();
+        let project = dir.path().join("deleted");
+
+        let result = Session::load(&project);
+        assert!(matches!(result, Err(EgoError::DirectoryMissing(path)) if path == project));
+    }
+
+    #[test]
+    fn retry_transient_retries_until_success() {
+        let mut attempts = 0;
+        let result = retry_transient(|| {
+            attempts += 1;
+            if attempts < 3 {
+                Err(io::Error::from(io::ErrorKind::Interrupted))
+            } else {
+                Ok(attempts)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+    }
+
+    #[test]
+    fn retry_transient_gives_up_after_last_attempt() {
+        let mut attempts = 0;
+        let result: io::Result<()> = retry_transient(|| {
+            attempts += 1;
+            Err(io::Error::from(io::ErrorKind::TimedOut))
+        });
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert_eq!(attempts, SAVE_ATTEMPTS);
+    }
 
-        let mut total_lines: i32 = 0;
-        visit_dirs(dir, &mut total_lines)?;
-        Ok(total_lines)
+    #[test]
+    fn retry_transient_returns_other_errors_at_once() {
+        let mut attempts = 0;
+        let result: io::Result<()> = retry_transient(|| {
+            attempts += 1;
+            Err(io::Error::from(io::ErrorKind::PermissionDenied))
+        });
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(attempts, 1);
     }
 }
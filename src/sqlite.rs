@@ -0,0 +1,262 @@
+//! The optional SQLite history store, with sessions and their file changes
+//! in separate tables so that the history can be queried with SQL.
+
+#[cfg(feature = "sqlite")]
+pub use store::Store;
+
+#[cfg(feature = "sqlite")]
+mod store {
+    use crate::history::{HistoryEntry, PruneOptions};
+    use crate::machine::Machine;
+    use crate::session::ChangeKind;
+    use anyhow::Result;
+    use chrono::{DateTime, Local, Utc};
+    use rusqlite::{params, Connection, Row};
+    use std::path::{Path, PathBuf};
+
+    const SCHEMA: &str = "
+        CREATE TABLE IF NOT EXISTS sessions (
+            id TEXT PRIMARY KEY,
+            start_time TEXT NOT NULL,
+            end_time TEXT NOT NULL,
+            project_directory TEXT NOT NULL,
+            project_name TEXT NOT NULL,
+            initial_line_count INTEGER NOT NULL,
+            final_line_count INTEGER NOT NULL,
+            lines_written INTEGER NOT NULL,
+            active_time_seconds INTEGER NOT NULL,
+            activity_samples TEXT NOT NULL,
+            mood INTEGER,
+            energy INTEGER,
+            host TEXT,
+            os TEXT,
+            editor TEXT,
+            chars_written INTEGER NOT NULL DEFAULT 0,
+            files_changed INTEGER NOT NULL DEFAULT 0,
+            paused_secs INTEGER NOT NULL DEFAULT 0,
+            lines_added INTEGER
+        );
+        CREATE INDEX IF NOT EXISTS sessions_start_time ON sessions (start_time);
+        CREATE INDEX IF NOT EXISTS sessions_end_time ON sessions (end_time);
+        CREATE TABLE IF NOT EXISTS file_changes (
+            session_id TEXT NOT NULL REFERENCES sessions (id) ON DELETE CASCADE,
+            path TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            initial_lines INTEGER NOT NULL,
+            final_lines INTEGER NOT NULL,
+            renamed_from TEXT
+        );
+        CREATE INDEX IF NOT EXISTS file_changes_session ON file_changes (session_id);
+    ";
+
+    const COLUMNS: &str = "id, start_time, end_time, project_directory, project_name, \
+        initial_line_count, final_line_count, lines_written, active_time_seconds, \
+        activity_samples, mood, energy, host, os, editor, chars_written, files_changed, \
+        paused_secs, lines_added";
+
+    pub struct Store {
+        connection: Connection,
+    }
+
+    impl Store {
+        pub fn open(path: &Path) -> Result<Self> {
+            let connection = Connection::open(path)?;
+            connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+            connection.execute_batch(SCHEMA)?;
+            migrate(&connection)?;
+            Ok(Store { connection })
+        }
+
+        pub fn append(&mut self, entry: &HistoryEntry) -> Result<()> {
+            let machine = entry.machine.as_ref();
+            let transaction = self.connection.transaction()?;
+            transaction.execute(
+                &format!(
+                    "INSERT OR REPLACE INTO sessions ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)",
+                    COLUMNS
+                ),
+                params![
+                    entry.id,
+                    entry.start_time.with_timezone(&Utc),
+                    entry.end_time.with_timezone(&Utc),
+                    entry.project_directory.to_string_lossy(),
+                    entry.project_name,
+                    entry.initial_line_count,
+                    entry.final_line_count,
+                    entry.lines_written,
+                    entry.active_time_seconds,
+                    serde_json::to_string(&entry.activity_samples)?,
+                    entry.mood,
+                    entry.energy,
+                    machine.and_then(|machine| machine.host.as_deref()),
+                    machine.map(|machine| machine.os.as_str()),
+                    machine.and_then(|machine| machine.editor.as_deref()),
+                    entry.chars_written,
+                    entry.files_changed as i64,
+                    entry.paused_secs,
+                    entry.lines_added,
+                ],
+            )?;
+            for change in &entry.file_changes {
+                transaction.execute(
+                    "INSERT INTO file_changes (session_id, path, kind, initial_lines, final_lines, renamed_from) \
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        entry.id,
+                        change.path.to_string_lossy(),
+                        match change.kind {
+                            ChangeKind::Created => "created",
+                            ChangeKind::Modified => "modified",
+                            ChangeKind::Deleted => "deleted",
+                            ChangeKind::Renamed => "renamed",
+                        },
+                        change.initial_lines,
+                        change.final_lines,
+                        change
+                            .renamed_from
+                            .as_ref()
+                            .map(|path| path.to_string_lossy().into_owned()),
+                    ],
+                )?;
+            }
+            transaction.commit()?;
+            Ok(())
+        }
+
+        /// Sessions that started at or after `since`, or all of them, oldest
+        /// first.
+        pub fn load(&self, since: Option<DateTime<Local>>) -> Result<Vec<HistoryEntry>> {
+            let mut statement = self.connection.prepare(&format!(
+                "SELECT {} FROM sessions WHERE ?1 IS NULL OR start_time >= ?1 ORDER BY start_time",
+                COLUMNS
+            ))?;
+            let entries = statement
+                .query_map(params![since.map(|since| since.with_timezone(&Utc))], entry)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(entries)
+        }
+
+        /// Removes the sessions selected by `options` and returns them, with
+        /// `keep_last` counting the most recently ended sessions.
+        pub fn prune(
+            &mut self,
+            options: &PruneOptions,
+            now: DateTime<Local>,
+        ) -> Result<Vec<HistoryEntry>> {
+            let cutoff = options
+                .older_than
+                .map(|age| (now - age).with_timezone(&Utc));
+            let mut statement = self.connection.prepare(&format!(
+                "SELECT {} FROM sessions
+                 WHERE (?1 IS NULL OR end_time < ?1)
+                   AND id NOT IN (SELECT id FROM sessions ORDER BY end_time DESC LIMIT ?2)
+                 ORDER BY end_time",
+                COLUMNS
+            ))?;
+            let keep_last = options.keep_last.map_or(0, |keep| keep as i64);
+            let removed = statement
+                .query_map(params![cutoff, keep_last], entry)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            drop(statement);
+
+            if !options.dry_run {
+                let transaction = self.connection.transaction()?;
+                for entry in &removed {
+                    transaction.execute("DELETE FROM sessions WHERE id = ?1", params![entry.id])?;
+                }
+                transaction.commit()?;
+            }
+            Ok(removed)
+        }
+    }
+
+    /// Adds the columns that databases created by older versions lack.
+    fn migrate(connection: &Connection) -> Result<()> {
+        for (column, alter) in [
+            (
+                "mood",
+                "ALTER TABLE sessions ADD COLUMN mood INTEGER;
+                 ALTER TABLE sessions ADD COLUMN energy INTEGER;",
+            ),
+            (
+                "host",
+                "ALTER TABLE sessions ADD COLUMN host TEXT;
+                 ALTER TABLE sessions ADD COLUMN os TEXT;
+                 ALTER TABLE sessions ADD COLUMN editor TEXT;",
+            ),
+            (
+                "chars_written",
+                "ALTER TABLE sessions ADD COLUMN chars_written INTEGER NOT NULL DEFAULT 0;
+                 ALTER TABLE sessions ADD COLUMN files_changed INTEGER NOT NULL DEFAULT 0;",
+            ),
+            (
+                "paused_secs",
+                "ALTER TABLE sessions ADD COLUMN paused_secs INTEGER NOT NULL DEFAULT 0;",
+            ),
+            (
+                "lines_added",
+                "ALTER TABLE sessions ADD COLUMN lines_added INTEGER;",
+            ),
+        ] {
+            let exists = connection
+                .prepare("SELECT 1 FROM pragma_table_info('sessions') WHERE name = ?1")?
+                .exists([column])?;
+            if !exists {
+                connection.execute_batch(alter)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn entry(row: &Row) -> rusqlite::Result<HistoryEntry> {
+        let samples: String = row.get(9)?;
+        let (host, os, editor): (_, Option<String>, _) = (row.get(12)?, row.get(13)?, row.get(14)?);
+        Ok(HistoryEntry {
+            id: row.get(0)?,
+            start_time: row.get::<_, DateTime<Utc>>(1)?.with_timezone(&Local),
+            end_time: row.get::<_, DateTime<Utc>>(2)?.with_timezone(&Local),
+            project_directory: PathBuf::from(row.get::<_, String>(3)?),
+            project_name: row.get(4)?,
+            initial_line_count: row.get(5)?,
+            final_line_count: row.get(6)?,
+            lines_written: row.get(7)?,
+            lines_added: row.get(18)?,
+            chars_written: row.get(15)?,
+            files_changed: row.get::<_, i64>(16)? as usize,
+            active_time_seconds: row.get(8)?,
+            paused_secs: row.get(17)?,
+            activity_samples: serde_json::from_str(&samples).unwrap_or_default(),
+            mood: row.get(10)?,
+            energy: row.get(11)?,
+            machine: os.map(|os| Machine { host, os, editor }),
+            file_changes: Vec::new(),
+        })
+    }
+}
+
+/// Stands in for the store in builds without the `sqlite` feature, where it
+/// can never be opened.
+#[cfg(not(feature = "sqlite"))]
+pub enum Store {}
+
+#[cfg(not(feature = "sqlite"))]
+impl Store {
+    pub fn append(&mut self, _: &crate::history::HistoryEntry) -> anyhow::Result<()> {
+        match *self {}
+    }
+
+    pub fn load(
+        &self,
+        _: Option<chrono::DateTime<chrono::Local>>,
+    ) -> anyhow::Result<Vec<crate::history::HistoryEntry>> {
+        match *self {}
+    }
+
+    pub fn prune(
+        &mut self,
+        _: &crate::history::PruneOptions,
+        _: chrono::DateTime<chrono::Local>,
+    ) -> anyhow::Result<Vec<crate::history::HistoryEntry>> {
+        match *self {}
+    }
+}
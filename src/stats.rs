@@ -0,0 +1,841 @@
+use crate::git::BranchComparison;
+use crate::locale::Locale;
+use crate::machine::Machine;
+use crate::plugin::CommandMetric;
+use crate::scan;
+use crate::scan::{ContentMetrics, Metric};
+use crate::session::{ChangeKind, FileChange, Rename, Session, SubprojectChanges};
+use crate::terms::TermCount;
+use crate::tokei::TokeiCounts;
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Component, Path, PathBuf};
+
+/// How summaries describe a session below the minor change threshold.
+const MINOR_CHANGES: &str = "minor changes";
+/// The width of each window in `SessionStats::activity_buckets`.
+pub const ACTIVITY_BUCKET_SECS: i64 = 600;
+
+#[derive(Serialize, Deserialize)]
+pub struct SessionStats {
+    pub id: String,
+    pub project_name: String,
+    pub project_directory: PathBuf,
+    pub start_time: DateTime<Local>,
+    pub end_time: DateTime<Local>,
+    /// Time from start to end, less time spent paused.
+    pub duration_secs: i64,
+    pub active_secs: i64,
+    /// Reports saved by older versions lack this and read it as 0;
+    /// `idle_secs()` works for both.
+    #[serde(default)]
+    pub idle_secs: i64,
+    #[serde(default)]
+    pub paused_secs: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_rounded_to_secs: Option<i64>,
+    pub break_durations: Vec<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_to_first_edit_secs: Option<i64>,
+    /// Minutes with recorded activity in each `ACTIVITY_BUCKET_SECS` window
+    /// from start to end, including time paused. Empty without activity
+    /// tracking.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub activity_buckets: Vec<u64>,
+    pub initial_line_count: i32,
+    pub final_line_count: i32,
+    pub lines_written: i32,
+    #[serde(default)]
+    pub lines_added: i32,
+    #[serde(default)]
+    pub lines_removed: i32,
+    #[serde(default)]
+    pub chars_written: i32,
+    pub initial_metrics: ContentMetrics,
+    pub final_metrics: ContentMetrics,
+    /// Metrics to show side by side in the report.
+    #[serde(skip, default = "all_metrics")]
+    pub metrics_shown: Vec<Metric>,
+    /// Below this many lines added plus removed, summaries just say "minor
+    /// changes".
+    #[serde(skip)]
+    pub minor_change_lines: Option<u32>,
+    /// How the breakdown table groups changed files.
+    #[serde(skip)]
+    pub group_by: GroupBy,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub initial_prose_words: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub final_prose_words: Option<i32>,
+    pub files_created: Vec<PathBuf>,
+    pub files_modified: Vec<PathBuf>,
+    pub files_deleted: Vec<PathBuf>,
+    pub files_renamed: Vec<Rename>,
+    pub files_moved_out: Vec<Rename>,
+    pub files_moved_in: Vec<Rename>,
+    pub subprojects: Vec<SubprojectChanges>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub top_terms: Vec<TermCount>,
+    pub file_changes: Vec<FileChange>,
+    pub generated_files_changed: Vec<PathBuf>,
+    pub generated_lines_written: i32,
+    pub test_lines_written: i32,
+    pub files_skipped: usize,
+    pub small_files_omitted: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch_comparison: Option<BranchComparison>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub history_comparison: Option<HistoryComparison>,
+    /// Distinct files changed at any point, when activity was tracked by
+    /// watching the project.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub files_touched: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mood: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub energy: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub goal_lines: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub goal_minutes: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command_metric: Option<CommandMetric>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokei: Option<TokeiCounts>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub machine: Option<Machine>,
+}
+
+fn all_metrics() -> Vec<Metric> {
+    Metric::ALL.to_vec()
+}
+
+/// The user's recent average pace, to compare the session against.
+#[derive(Serialize, Deserialize)]
+pub struct HistoryComparison {
+    pub days: i64,
+    pub average_lines_per_active_hour: f64,
+}
+
+impl SessionStats {
+    pub fn new(session: &Session, end_time: DateTime<Local>) -> Self {
+        let durations = split_durations(
+            (end_time - session.start_time).num_seconds() - session.paused_secs_at(end_time),
+            session.active_time_seconds,
+        );
+        let final_metrics = session.final_metrics.unwrap_or_default();
+        let (lines_added, lines_removed) = line_totals(&session.file_changes);
+        SessionStats {
+            id: session.id.clone(),
+            project_name: session.project_name.clone(),
+            project_directory: session.project_directory.clone(),
+            start_time: session.start_time,
+            end_time,
+            duration_secs: durations.total_secs,
+            active_secs: durations.active_secs,
+            idle_secs: durations.idle_secs,
+            paused_secs: session.paused_secs_at(end_time),
+            active_rounded_to_secs: None,
+            break_durations: session.breaks.iter().map(|b| b.duration_secs).collect(),
+            activity_buckets: activity_buckets(
+                &session.activity_samples,
+                session.start_time,
+                end_time,
+            ),
+            time_to_first_edit_secs: session
+                .first_activity
+                .map(|first| (first - session.start_time).num_seconds().max(0)),
+            initial_line_count: session.initial_line_count,
+            final_line_count: session.final_line_count.unwrap_or(0),
+            lines_written: session.lines_written.unwrap_or(0),
+            lines_added,
+            lines_removed,
+            chars_written: final_metrics.chars - session.initial_metrics.chars,
+            initial_metrics: session.initial_metrics,
+            final_metrics,
+            metrics_shown: all_metrics(),
+            minor_change_lines: None,
+            group_by: GroupBy::default(),
+            initial_prose_words: session.initial_prose_words,
+            final_prose_words: session.final_prose_words,
+            files_created: session.files_created.clone(),
+            files_modified: session.files_modified.clone(),
+            files_deleted: session.files_deleted.clone(),
+            files_renamed: session.files_renamed.clone(),
+            files_moved_out: session.files_moved_out.clone(),
+            files_moved_in: session.files_moved_in.clone(),
+            subprojects: session.subprojects.clone(),
+            top_terms: session.top_terms.clone(),
+            file_changes: session.file_changes.clone(),
+            generated_files_changed: session.generated_files_changed.clone(),
+            generated_lines_written: session.generated_lines_written,
+            test_lines_written: session.test_lines_written,
+            files_skipped: session.files_skipped.len(),
+            small_files_omitted: session.small_files_omitted.len(),
+            branch_comparison: None,
+            history_comparison: None,
+            files_touched: (!session.files_touched.is_empty())
+                .then_some(session.files_touched.len()),
+            mood: session.mood,
+            energy: session.energy,
+            goal_lines: session.goal_lines,
+            goal_minutes: session.goal_minutes,
+            command_metric: session.command_metric.clone(),
+            tokei: session.tokei.clone(),
+            machine: session.machine.clone(),
+        }
+    }
+
+    /// Reports active time rounded up to a whole number of `increment_secs`,
+    /// as when billing in fixed increments. The session itself keeps the raw
+    /// value.
+    pub fn round_active(&mut self, increment_secs: i64) {
+        self.active_secs = round_up(self.active_secs, increment_secs);
+        self.idle_secs = self.idle_secs();
+        self.active_rounded_to_secs = Some(increment_secs);
+    }
+
+    pub fn idle_secs(&self) -> i64 {
+        split_durations(self.duration_secs, self.active_secs).idle_secs
+    }
+
+    pub fn total_break_secs(&self) -> i64 {
+        self.break_durations.iter().sum()
+    }
+
+    pub fn longest_break_secs(&self) -> i64 {
+        self.break_durations.iter().copied().max().unwrap_or(0)
+    }
+
+    /// Share of the session spent active, clamped to `0.0..=1.0` so that
+    /// activity recorded past the wall-clock span never overflows the bar.
+    pub fn active_ratio(&self) -> f64 {
+        split_durations(self.duration_secs, self.active_secs).active_ratio
+    }
+
+    /// Lines added per hour, so rewriting code counts as much as writing
+    /// new code.
+    pub fn lines_per_hour(&self) -> f64 {
+        if self.duration_secs <= 0 {
+            return 0.0;
+        }
+        line_totals(&self.file_changes).0 as f64 * 3600.0 / self.duration_secs as f64
+    }
+
+    pub fn lines_per_active_hour(&self) -> f64 {
+        if self.active_secs <= 0 {
+            return 0.0;
+        }
+        line_totals(&self.file_changes).0 as f64 * 3600.0 / self.active_secs as f64
+    }
+
+    /// Lines written outside test code.
+    pub fn production_lines_written(&self) -> i32 {
+        self.lines_written - self.test_lines_written
+    }
+
+    /// Whether the session removed more lines than it added, which the
+    /// report presents as cleanup rather than as a loss.
+    pub fn is_cleanup(&self) -> bool {
+        self.lines_written < 0
+    }
+
+    /// Whether the session changed fewer lines than `minor_change_lines`.
+    pub fn is_minor_change(&self) -> bool {
+        let (added, removed) = line_totals(&self.file_changes);
+        self.minor_change_lines
+            .is_some_and(|threshold| ((added + removed) as u32) < threshold)
+    }
+
+    /// Distinct files changed per hour of active time, as a measure of how
+    /// broad the session's work was.
+    pub fn files_per_active_hour(&self) -> f64 {
+        if self.active_secs <= 0 {
+            return 0.0;
+        }
+        self.file_changes.len() as f64 * 3600.0 / self.active_secs as f64
+    }
+
+    /// The changes per group, keyed as `group_by` says, largest line change
+    /// first. Groups whose lines and characters came out even are left out.
+    pub fn breakdown(&self) -> Vec<(String, GroupChange)> {
+        let mut breakdown: BTreeMap<String, GroupChange> = BTreeMap::new();
+        for change in &self.file_changes {
+            let key = match self.group_by {
+                GroupBy::Extension => change
+                    .path
+                    .extension()
+                    .map(|ext| format!(".{}", ext.to_string_lossy()))
+                    .unwrap_or_else(|| "(none)".to_string()),
+                GroupBy::Language => scan::language(&change.path).to_string(),
+                GroupBy::Dir => match self.relative_path(&change.path).parent() {
+                    Some(dir) if !dir.as_os_str().is_empty() => dir.display().to_string(),
+                    _ => ".".to_string(),
+                },
+            };
+            let group = breakdown.entry(key).or_default();
+            group.files += 1;
+            group.lines += change.delta();
+            group.chars += change.char_delta();
+        }
+        let mut breakdown: Vec<_> = breakdown
+            .into_iter()
+            .filter(|(_, group)| group.lines != 0 || group.chars != 0)
+            .collect();
+        breakdown.sort_by_key(|(_, group)| std::cmp::Reverse(group.lines.abs()));
+        breakdown
+    }
+
+    pub fn relative_path<'a>(&self, path: &'a Path) -> &'a Path {
+        path.strip_prefix(&self.project_directory).unwrap_or(path)
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct GroupChange {
+    pub files: usize,
+    pub lines: i32,
+    pub chars: i32,
+}
+
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum GroupBy {
+    /// Strictly by file suffix, so `.h` and `.hpp` stay apart.
+    Extension,
+    #[default]
+    Language,
+    /// By the directory containing the file.
+    Dir,
+}
+
+impl GroupBy {
+    pub fn label(self) -> &'static str {
+        match self {
+            GroupBy::Extension => "Extension",
+            GroupBy::Language => "Language",
+            GroupBy::Dir => "Directory",
+        }
+    }
+
+    pub fn heading(self) -> &'static str {
+        match self {
+            GroupBy::Extension => "Extensions",
+            GroupBy::Language => "Languages",
+            GroupBy::Dir => "Directories",
+        }
+    }
+}
+
+pub struct Durations {
+    pub total_secs: i64,
+    pub active_secs: i64,
+    pub idle_secs: i64,
+    pub active_ratio: f64,
+}
+
+/// Splits a session's wall-clock span into active and idle time. A negative
+/// total (a clock that went backwards) counts as zero, and active time is
+/// clamped to the total, so active plus idle always adds up to the total and
+/// the ratio stays within `0.0..=1.0`.
+pub fn split_durations(total_secs: i64, active_secs: i64) -> Durations {
+    let total_secs = total_secs.max(0);
+    let active_secs = active_secs.clamp(0, total_secs);
+    let active_ratio = if total_secs > 0 {
+        active_secs as f64 / total_secs as f64
+    } else {
+        0.0
+    };
+    Durations {
+        total_secs,
+        active_secs,
+        idle_secs: total_secs - active_secs,
+        active_ratio,
+    }
+}
+
+/// Counts the per-minute activity samples in each `ACTIVITY_BUCKET_SECS`
+/// window between `start` and `end`.
+pub fn activity_buckets(
+    samples: &[DateTime<Local>],
+    start: DateTime<Local>,
+    end: DateTime<Local>,
+) -> Vec<u64> {
+    if samples.is_empty() {
+        return Vec::new();
+    }
+    let span = (end - start).num_seconds().max(1) as u64;
+    let mut buckets = vec![0; span.div_ceil(ACTIVITY_BUCKET_SECS as u64) as usize];
+    for sample in samples {
+        let offset = (*sample - start).num_seconds();
+        if offset < 0 {
+            continue;
+        }
+        if let Some(bucket) = buckets.get_mut((offset / ACTIVITY_BUCKET_SECS) as usize) {
+            *bucket += 1;
+        }
+    }
+    buckets
+}
+
+/// Lines added and removed across `changes`.
+pub fn line_totals(changes: &[FileChange]) -> (i32, i32) {
+    changes.iter().fold((0, 0), |(added, removed), change| {
+        let (file_added, file_removed) = change.added_removed();
+        (added + file_added, removed + file_removed)
+    })
+}
+
+/// Rounds `secs` up to the next multiple of `increment_secs`.
+pub fn round_up(secs: i64, increment_secs: i64) -> i64 {
+    if increment_secs <= 0 {
+        return secs;
+    }
+    (secs.max(0) + increment_secs - 1) / increment_secs * increment_secs
+}
+
+#[derive(Serialize)]
+pub struct StatusReport {
+    pub id: String,
+    pub project_name: String,
+    pub project_directory: PathBuf,
+    pub start_time: DateTime<Local>,
+    /// Time since the start, less time spent paused.
+    pub elapsed_secs: i64,
+    pub active_secs: i64,
+    pub idle_secs: i64,
+    pub paused_secs: i64,
+    pub paused: bool,
+    pub initial_line_count: i32,
+    pub current_line_count: i32,
+    pub lines_written: i32,
+    pub initial_char_count: i32,
+    pub current_char_count: i32,
+    pub chars_written: i32,
+    /// Only filled in by `ego status`, which compares the files against the
+    /// start snapshot.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub file_changes: Vec<FileChange>,
+}
+
+impl StatusReport {
+    pub fn new(session: &Session, current: &ContentMetrics, now: DateTime<Local>) -> Self {
+        let durations = split_durations(
+            (now - session.start_time).num_seconds() - session.paused_secs_at(now),
+            session.active_time_seconds,
+        );
+        StatusReport {
+            id: session.id.clone(),
+            project_name: session.project_name.clone(),
+            project_directory: session.project_directory.clone(),
+            start_time: session.start_time,
+            elapsed_secs: (now - session.start_time).num_seconds() - session.paused_secs_at(now),
+            active_secs: durations.active_secs,
+            idle_secs: durations.idle_secs,
+            paused_secs: session.paused_secs_at(now),
+            paused: session.paused_at.is_some(),
+            initial_line_count: session.initial_line_count,
+            current_line_count: current.lines,
+            lines_written: current.lines - session.initial_line_count,
+            initial_char_count: session.initial_metrics.chars,
+            current_char_count: current.chars,
+            chars_written: current.chars - session.initial_metrics.chars,
+            file_changes: Vec::new(),
+        }
+    }
+}
+
+pub struct GoalProgress {
+    pub name: &'static str,
+    pub title: &'static str,
+    /// Such as `230 / 300 (77%)`.
+    pub progress: String,
+    /// How much of the goal was reached, `1.0` or more once it is met.
+    pub ratio: f64,
+}
+
+impl GoalProgress {
+    fn new(
+        (name, title): (&'static str, &'static str),
+        done: String,
+        goal: String,
+        ratio: f64,
+    ) -> Self {
+        let ratio = if ratio.is_finite() {
+            ratio.max(0.0)
+        } else {
+            1.0
+        };
+        GoalProgress {
+            name,
+            title,
+            progress: format!("{} / {} ({:.0}%)", done, goal, ratio * 100.0),
+            ratio,
+        }
+    }
+
+    pub fn is_met(&self) -> bool {
+        self.ratio >= 1.0
+    }
+}
+
+pub struct StatsFormatter {
+    locale: Locale,
+    emoji: bool,
+}
+
+impl StatsFormatter {
+    pub fn new(locale: Locale, emoji: bool) -> Self {
+        StatsFormatter {
+            locale,
+            emoji: emoji && emoji_supported(),
+        }
+    }
+
+    pub fn count(&self, value: i32) -> String {
+        self.locale.format_int(value as i64)
+    }
+
+    pub fn signed_count(&self, value: i32) -> String {
+        let sign = if value >= 0 { "+" } else { "" };
+        format!("{}{}", sign, self.count(value))
+    }
+
+    /// Progress towards the session's goals: lines written against
+    /// `goal_lines` and session duration against `goal_minutes`.
+    pub fn goals(&self, stats: &SessionStats) -> Vec<GoalProgress> {
+        let mut goals = Vec::new();
+        if let Some(goal) = stats.goal_lines {
+            goals.push(GoalProgress::new(
+                ("Lines", "Lines goal"),
+                self.count(stats.lines_written),
+                self.count(goal),
+                stats.lines_written as f64 / goal as f64,
+            ));
+        }
+        if let Some(goal) = stats.goal_minutes {
+            goals.push(GoalProgress::new(
+                ("Time", "Time goal"),
+                self.short_duration(stats.duration_secs),
+                self.short_duration(goal as i64 * 60),
+                stats.duration_secs as f64 / (goal as f64 * 60.0),
+            ));
+        }
+        goals
+    }
+
+    pub fn rate(&self, value: f64) -> String {
+        self.locale.format_float(value, 1)
+    }
+
+    pub fn datetime(&self, datetime: &DateTime<Local>) -> String {
+        self.locale.format_datetime(datetime)
+    }
+
+    /// Renders a path for display, without the quoting and escaping of `{:?}`
+    /// and without redundant `./` segments.
+    pub fn path(&self, path: &Path) -> String {
+        let cleaned: PathBuf = path
+            .components()
+            .filter(|component| !matches!(component, Component::CurDir))
+            .collect();
+        if cleaned.as_os_str().is_empty() {
+            ".".to_string()
+        } else {
+            cleaned.display().to_string()
+        }
+    }
+
+    /// The lines added and removed, as in "+150 / −100", or for a cleanup
+    /// session "Removed 230 lines (cleanup)".
+    pub fn lines_written(&self, stats: &SessionStats) -> String {
+        if stats.is_cleanup() {
+            format!(
+                "Removed {} lines (cleanup)",
+                self.count(-stats.lines_written)
+            )
+        } else {
+            let (added, removed) = line_totals(&stats.file_changes);
+            format!("+{} / −{}", self.count(added), self.count(removed))
+        }
+    }
+
+    /// One-line human-readable summary of a finished session.
+    pub fn summary(&self, stats: &SessionStats) -> String {
+        let changes = if stats.is_minor_change() {
+            MINOR_CHANGES.to_string()
+        } else if stats.is_cleanup() {
+            format!(
+                "removed {} lines (cleanup), {} files changed",
+                self.count(-stats.lines_written),
+                self.count(stats.file_changes.len() as i32)
+            )
+        } else {
+            format!(
+                "{} lines, {} files changed",
+                self.signed_count(stats.lines_written),
+                self.count(stats.file_changes.len() as i32)
+            )
+        };
+        format!(
+            "{} {} – {}: {} for {} (active {}), {}",
+            stats.start_time.format("%Y-%m-%d"),
+            stats.start_time.format("%H:%M"),
+            stats.end_time.format("%H:%M"),
+            stats.project_name,
+            self.short_duration(stats.duration_secs),
+            self.short_duration(stats.active_secs),
+            changes
+        )
+    }
+
+    /// A terse one-line summary such as `1h23m active · +140/−30 lines · 4 files`,
+    /// or `5m active · minor changes` below the minor change threshold.
+    /// Numbers are never localized, so the line is easy to parse.
+    pub fn one_line(&self, stats: &SessionStats) -> String {
+        let active = self.short_duration(stats.active_secs);
+        if stats.is_minor_change() {
+            return format!("{} active · {}", active, MINOR_CHANGES);
+        }
+        let (added, removed) = line_totals(&stats.file_changes);
+        format!(
+            "{} active · +{}/−{} lines · {} files",
+            active,
+            added,
+            removed,
+            stats.file_changes.len()
+        )
+    }
+
+    /// The mood and energy ratings of a session, as in "Mood 4/5 · Energy
+    /// 2/5", or `None` when neither was given.
+    pub fn ratings(&self, mood: Option<u8>, energy: Option<u8>) -> Option<String> {
+        let parts: Vec<String> = [("Mood", mood), ("Energy", energy)]
+            .into_iter()
+            .filter_map(|(label, rating)| rating.map(|rating| format!("{} {}/5", label, rating)))
+            .collect();
+        (!parts.is_empty()).then(|| parts.join(" · "))
+    }
+
+    /// Describes where a session took place, as in "laptop · linux · nvim".
+    pub fn machine(&self, machine: &Machine) -> String {
+        [
+            machine.host.as_deref(),
+            Some(machine.os.as_str()),
+            machine.editor.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" · ")
+    }
+
+    /// Compares the session's pace with the historical average, as in
+    /// "180 lines/active-hour — 20% above your 30-day average". `None` when
+    /// there is nothing to compare.
+    pub fn history_comparison(&self, stats: &SessionStats) -> Option<String> {
+        let comparison = stats.history_comparison.as_ref()?;
+        if stats.active_secs <= 0 || comparison.average_lines_per_active_hour <= 0.0 {
+            return None;
+        }
+        let rate = stats.lines_per_active_hour();
+        let percent = (rate / comparison.average_lines_per_active_hour - 1.0) * 100.0;
+        let relation = if percent.round() == 0.0 {
+            "on par with".to_string()
+        } else if percent > 0.0 {
+            format!("{}% above", self.count(percent.round() as i32))
+        } else {
+            format!("{}% below", self.count(-percent.round() as i32))
+        };
+        Some(format!(
+            "{} lines/active-hour — {} your {}-day average",
+            self.rate(rate),
+            relation,
+            comparison.days
+        ))
+    }
+
+    /// Describes how a metric changed, as in "1200 → 1350 (+150)".
+    pub fn metric_change(&self, stats: &SessionStats, metric: Metric) -> String {
+        let initial = stats.initial_metrics.get(metric);
+        let last = stats.final_metrics.get(metric);
+        format!(
+            "{} → {} ({})",
+            self.count(initial),
+            self.count(last),
+            self.signed_count(last - initial)
+        )
+    }
+
+    /// Renders a count at the end of the session with its change, as in
+    /// "1,250 (+50)".
+    pub fn count_with_change(&self, initial: i64, last: i64) -> String {
+        let change = last - initial;
+        format!(
+            "{} ({}{})",
+            self.locale.format_int(last),
+            if change >= 0 { "+" } else { "" },
+            self.locale.format_int(change)
+        )
+    }
+
+    /// Describes a value from a metric command as `initial → last (change)`.
+    pub fn command_metric_change(&self, initial: f64, last: f64) -> String {
+        let number = |value: f64| {
+            if value.fract() == 0.0 {
+                self.locale.format_int(value as i64)
+            } else {
+                self.locale.format_float(value, 2)
+            }
+        };
+        let change = last - initial;
+        format!(
+            "{} → {} ({}{})",
+            number(initial),
+            number(last),
+            if change >= 0.0 { "+" } else { "" },
+            number(change)
+        )
+    }
+
+    /// Lists terms with their counts, as in "parser (12), token (9)".
+    pub fn top_terms(&self, terms: &[TermCount]) -> String {
+        terms
+            .iter()
+            .map(|term| format!("{} ({})", term.term, self.count(term.count as i32)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Describes a rename as `old → new`, noting the estimated edits when the
+    /// contents changed too.
+    pub fn rename(&self, stats: &SessionStats, rename: &Rename) -> String {
+        let moved = format!(
+            "{} → {}",
+            self.path(stats.relative_path(&rename.from)),
+            self.path(stats.relative_path(&rename.to))
+        );
+        if rename.churn == 0 {
+            moved
+        } else {
+            format!(
+                "{} ({}% similar, ~{} lines changed)",
+                moved,
+                self.rate(rename.similarity * 100.0),
+                self.count(rename.churn)
+            )
+        }
+    }
+
+    pub fn change_icon(&self, kind: ChangeKind) -> &'static str {
+        match (kind, self.emoji) {
+            (ChangeKind::Created, true) => "➕",
+            (ChangeKind::Modified, true) => "✏️",
+            (ChangeKind::Deleted, true) => "🗑️",
+            (ChangeKind::Renamed, true) => "🔀",
+            (ChangeKind::Created, false) => "+",
+            (ChangeKind::Modified, false) => "~",
+            (ChangeKind::Deleted, false) => "-",
+            (ChangeKind::Renamed, false) => ">",
+        }
+    }
+
+    /// Compact duration such as `1h23m`, `42m` or `35s`.
+    pub fn short_duration(&self, secs: i64) -> String {
+        let secs = secs.max(0);
+        let hours = secs / 3600;
+        let minutes = (secs % 3600) / 60;
+        match (hours, minutes) {
+            (0, 0) => format!("{}s", secs),
+            (0, _) => format!("{}m", minutes),
+            _ => format!("{}h{:02}m", hours, minutes),
+        }
+    }
+
+    /// Formats a span as `HH:MM:SS`. A negative span, from a clock that went
+    /// backwards, shows as zero rather than as `-1:-1:-1`.
+    pub fn duration(&self, secs: i64) -> String {
+        let secs = secs.max(0);
+        let hours = secs / 3600;
+        let minutes = (secs % 3600) / 60;
+        let seconds = secs % 60;
+        format!("{:02}:{:02}:{:02}", hours, minutes, seconds)
+    }
+}
+
+/// The Linux virtual console and the legacy Windows console cannot render
+/// emoji, so fall back to ASCII icons there.
+fn emoji_supported() -> bool {
+    if env::var("TERM").is_ok_and(|term| term == "linux" || term == "dumb") {
+        return false;
+    }
+    !cfg!(windows) || env::var_os("WT_SESSION").is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_durations_normal() {
+        let durations = split_durations(3600, 900);
+        assert_eq!(durations.total_secs, 3600);
+        assert_eq!(durations.active_secs, 900);
+        assert_eq!(durations.idle_secs, 2700);
+        assert_eq!(durations.active_ratio, 0.25);
+    }
+
+    #[test]
+    fn split_durations_clamps_active_to_total() {
+        let durations = split_durations(600, 900);
+        assert_eq!(durations.total_secs, 600);
+        assert_eq!(durations.active_secs, 600);
+        assert_eq!(durations.idle_secs, 0);
+        assert_eq!(durations.active_ratio, 1.0);
+    }
+
+    #[test]
+    fn active_past_total_formats_consistently() {
+        let formatter = StatsFormatter::new(Locale::default(), false);
+        let durations = split_durations(600, 900);
+        assert_eq!(formatter.duration(durations.total_secs), "00:10:00");
+        assert_eq!(formatter.duration(durations.active_secs), "00:10:00");
+        assert_eq!(formatter.duration(durations.idle_secs), "00:00:00");
+        assert_eq!(formatter.duration(600 - 900), "00:00:00");
+        assert_eq!(formatter.short_duration(600 - 900), "0s");
+    }
+
+    #[test]
+    fn path_is_shown_without_quotes() {
+        let formatter = StatsFormatter::new(Locale::default(), false);
+        assert_eq!(
+            formatter.path(Path::new("my project/src/main file.rs")),
+            "my project/src/main file.rs"
+        );
+        assert_eq!(formatter.path(Path::new("./src/lib.rs")), "src/lib.rs");
+        assert_eq!(formatter.path(Path::new("./")), ".");
+        let shown = formatter.path(Path::new("./my project"));
+        assert_eq!(shown, "my project");
+        assert!(!shown.contains('"'));
+    }
+
+    #[test]
+    fn split_durations_zero_active() {
+        let durations = split_durations(600, 0);
+        assert_eq!(durations.total_secs, 600);
+        assert_eq!(durations.active_secs, 0);
+        assert_eq!(durations.idle_secs, 600);
+        assert_eq!(durations.active_ratio, 0.0);
+    }
+
+    #[test]
+    fn split_durations_negative_total_counts_as_zero() {
+        let durations = split_durations(-120, 60);
+        assert_eq!(durations.total_secs, 0);
+        assert_eq!(durations.active_secs, 0);
+        assert_eq!(durations.idle_secs, 0);
+        assert_eq!(durations.active_ratio, 0.0);
+    }
+}
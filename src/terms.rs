@@ -0,0 +1,60 @@
+use crate::scan;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// How many terms a session reports.
+const TOP_TERMS: usize = 10;
+
+/// Identifiers shorter than this are mostly loop variables and keywords.
+const MIN_TERM_LENGTH: usize = 3;
+
+/// Keywords and filler common across the tracked languages, which would
+/// otherwise crowd out the terms that say what a session was about.
+const STOP_WORDS: [&str; 44] = [
+    "and", "are", "as", "async", "await", "bool", "break", "case", "char", "class", "const",
+    "continue", "def", "else", "enum", "false", "for", "from", "function", "impl", "import", "int",
+    "let", "match", "mut", "new", "none", "not", "null", "pub", "return", "self", "static",
+    "string", "struct", "the", "this", "true", "type", "use", "var", "void", "where", "while",
+];
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TermCount {
+    pub term: String,
+    pub count: usize,
+}
+
+/// Counts the identifiers and words on the lines of `path` whose hash isn't
+/// in `baseline`, the sorted line hashes of the file's previous contents.
+pub fn count_added(path: &Path, baseline: &[u64], counts: &mut BTreeMap<String, usize>) {
+    let Some(bytes) = scan::read_utf8(path) else {
+        return;
+    };
+    for line in bytes.split(|byte| *byte == b'\n') {
+        let line = line.trim_ascii();
+        if line.is_empty() || baseline.binary_search(&scan::line_hash(line)).is_ok() {
+            continue;
+        }
+        for term in String::from_utf8_lossy(line)
+            .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .filter(|term| term.chars().count() >= MIN_TERM_LENGTH)
+            .filter(|term| !term.starts_with(|c: char| c.is_ascii_digit()))
+        {
+            let term = term.to_lowercase();
+            if !STOP_WORDS.contains(&term.as_str()) {
+                *counts.entry(term).or_default() += 1;
+            }
+        }
+    }
+}
+
+/// The most frequent terms, most frequent first.
+pub fn top(counts: BTreeMap<String, usize>) -> Vec<TermCount> {
+    let mut terms: Vec<TermCount> = counts
+        .into_iter()
+        .map(|(term, count)| TermCount { term, count })
+        .collect();
+    terms.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.term.cmp(&b.term)));
+    terms.truncate(TOP_TERMS);
+    terms
+}
@@ -0,0 +1,84 @@
+//! Code, comment and blank line counts per language from the `tokei` binary,
+//! for builds with the `tokei` feature.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct CodeCounts {
+    pub code: i64,
+    pub comments: i64,
+    pub blanks: i64,
+}
+
+/// Tokei's counts for a project at the start of a session and, once it has
+/// ended, at the end.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TokeiCounts {
+    pub initial: BTreeMap<String, CodeCounts>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last: Option<BTreeMap<String, CodeCounts>>,
+}
+
+impl TokeiCounts {
+    pub fn start(dir: &Path) -> Result<Self> {
+        Ok(TokeiCounts {
+            initial: count(dir)?,
+            last: None,
+        })
+    }
+
+    pub fn finish(&mut self, dir: &Path) -> Result<()> {
+        self.last = Some(count(dir)?);
+        Ok(())
+    }
+
+    /// Each language found at the start or the end, with its counts at both,
+    /// as `(language, initial, last)`.
+    pub fn languages(&self) -> Vec<(&str, CodeCounts, CodeCounts)> {
+        let Some(last) = &self.last else {
+            return Vec::new();
+        };
+        let names: BTreeSet<&String> = self.initial.keys().chain(last.keys()).collect();
+        names
+            .into_iter()
+            .map(|name| {
+                (
+                    name.as_str(),
+                    self.initial.get(name).copied().unwrap_or_default(),
+                    last.get(name).copied().unwrap_or_default(),
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "tokei")]
+fn count(dir: &Path) -> Result<BTreeMap<String, CodeCounts>> {
+    use anyhow::{bail, Context};
+    use std::process::Command;
+
+    let output = Command::new("tokei")
+        .args(["--output", "json"])
+        .current_dir(dir)
+        .output()
+        .context("failed to run tokei; is it installed and on the PATH?")?;
+    if !output.status.success() {
+        bail!(
+            "tokei failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let mut counts: BTreeMap<String, CodeCounts> =
+        serde_json::from_slice(&output.stdout).context("tokei printed invalid JSON")?;
+    counts.remove("Total");
+    Ok(counts)
+}
+
+#[cfg(not(feature = "tokei"))]
+fn count(_dir: &Path) -> Result<BTreeMap<String, CodeCounts>> {
+    anyhow::bail!("tokei counts need ego to be built with the tokei feature")
+}
@@ -0,0 +1,155 @@
+use crate::idle;
+use crate::scan::FileFilter;
+use crate::session::Session;
+use anyhow::Result;
+use chrono::Local;
+use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const AWAY_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Records activity whenever a tracked file in the project is created,
+/// modified or removed, so it works regardless of which editor is used.
+///
+/// Where the OS can report it, changes made while the screen is locked or the
+/// user has been idle for `away_threshold` are ignored, and the time away is
+/// recorded as a break rather than active time.
+///
+/// Changes within `debounce` of the last recorded one are coalesced into it,
+/// so a formatter rewriting many files counts as a single tick of activity.
+pub fn track_file_changes(
+    session: &mut Session,
+    away_threshold: Duration,
+    debounce: Duration,
+) -> Result<()> {
+    let running = Arc::new(AtomicBool::new(true));
+    let handler_flag = running.clone();
+    ctrlc::set_handler(move || handler_flag.store(false, Ordering::SeqCst))?;
+
+    let root = fs::canonicalize(&session.project_directory)?;
+    let filter = FileFilter::new(&root, &session.options)?;
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    let mode = if session.options.max_depth == Some(0) {
+        RecursiveMode::NonRecursive
+    } else {
+        RecursiveMode::Recursive
+    };
+    watcher.watch(&root, mode)?;
+
+    let mut last_save = Instant::now();
+    let mut last_tick: Option<Instant> = None;
+    let mut last_away_check: Option<Instant> = None;
+    let mut away = false;
+    let mut was_away = false;
+    while running.load(Ordering::SeqCst) {
+        if last_away_check.is_none_or(|checked| checked.elapsed() >= AWAY_CHECK_INTERVAL) {
+            away = idle::user_is_away(away_threshold).unwrap_or(false);
+            was_away |= away;
+            last_away_check = Some(Instant::now());
+        }
+
+        match rx.recv_timeout(POLL_INTERVAL) {
+            Ok(Ok(event)) => {
+                let is_change = matches!(
+                    event.kind,
+                    EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                );
+                let tracked_paths: Vec<&Path> = event
+                    .paths
+                    .iter()
+                    .filter_map(|path| path.strip_prefix(&root).ok())
+                    .filter(|relative| {
+                        filter.tracks(relative) && session.options.within_depth(relative)
+                    })
+                    .collect();
+                if is_change && !away {
+                    for relative in &tracked_paths {
+                        let path = session.project_directory.join(relative);
+                        session.files_touched.insert(path);
+                    }
+                }
+                let debounced = last_tick.is_some_and(|tick| tick.elapsed() < debounce);
+                if is_change && !tracked_paths.is_empty() && !away && !debounced {
+                    last_tick = Some(Instant::now());
+                    if was_away {
+                        session.resume_activity(Local::now());
+                        was_away = false;
+                    } else {
+                        session.record_activity(Local::now());
+                    }
+                }
+            }
+            Ok(Err(err)) => eprintln!("Warning: file watcher error: {}", err),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+
+        if !flush(session, &mut last_save)? {
+            return Ok(());
+        }
+    }
+
+    save_if_active(session)
+}
+
+/// Records activity on every keypress in this terminal. Only useful when ego
+/// runs in a dedicated pane that has focus while you type.
+pub fn track_keystrokes(session: &mut Session) -> Result<()> {
+    enable_raw_mode()?;
+    let result = keystroke_loop(session);
+    disable_raw_mode()?;
+    result?;
+
+    save_if_active(session)
+}
+
+fn keystroke_loop(session: &mut Session) -> Result<()> {
+    let mut last_save = Instant::now();
+    loop {
+        if event::poll(POLL_INTERVAL)? {
+            if let Event::Key(key) = event::read()? {
+                if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                    return Ok(());
+                }
+                session.record_activity(Local::now());
+            }
+        }
+
+        if !flush(session, &mut last_save)? {
+            return Ok(());
+        }
+    }
+}
+
+/// Periodically writes the in-memory session back to disk, after picking up
+/// any `ego pause` or `ego resume` saved since. Returns `false` once the
+/// session file has been removed by `ego end`.
+fn flush(session: &mut Session, last_save: &mut Instant) -> Result<bool> {
+    if !session.is_active() {
+        return Ok(false);
+    }
+    session.sync_pause();
+    if last_save.elapsed() < SAVE_INTERVAL {
+        return Ok(true);
+    }
+
+    session.save()?;
+    *last_save = Instant::now();
+    Ok(true)
+}
+
+fn save_if_active(session: &Session) -> Result<()> {
+    if session.is_active() {
+        session.save()?;
+    }
+    Ok(())
+}
@@ -1,6 +1,9 @@
-use crate::session::Session;
+use crate::error::EgoError;
+use crate::report::{self, Summary};
+use crate::session::{ChangeKind, FileChange, Session};
+use crate::stats::{self, SessionStats, StatsFormatter, StatusReport};
 use anyhow::Result;
-use chrono::{DateTime, Local};
+use chrono::Local;
 use crossterm::{
     event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
     execute,
@@ -8,106 +11,1265 @@ use crossterm::{
 };
 use ratatui::{
     backend::CrosstermBackend,
-    layout::{Alignment, Constraint, Direction, Layout},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Paragraph},
-    Terminal,
+    widgets::{Block, Borders, Cell, Gauge, Paragraph, Row, Sparkline, Table, TableState},
+    Frame, Terminal, TerminalOptions, Viewport,
 };
-use std::io;
-use std::time::Duration;
+use std::io::{self, Stdout};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
-pub fn draw_stats(session: &Session, end_time: DateTime<Local>) -> Result<()> {
+#[derive(Clone, Copy)]
+enum FileSort {
+    Delta,
+    Path,
+    FinalLines,
+}
+
+impl FileSort {
+    fn next(self) -> Self {
+        match self {
+            FileSort::Delta => FileSort::Path,
+            FileSort::Path => FileSort::FinalLines,
+            FileSort::FinalLines => FileSort::Delta,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            FileSort::Delta => "change",
+            FileSort::Path => "path",
+            FileSort::FinalLines => "size",
+        }
+    }
+
+    fn sort(self, changes: &mut [FileChange]) {
+        match self {
+            FileSort::Delta => changes.sort_by(|a, b| {
+                b.delta()
+                    .abs()
+                    .cmp(&a.delta().abs())
+                    .then_with(|| a.path.cmp(&b.path))
+            }),
+            FileSort::Path => changes.sort_by(|a, b| a.path.cmp(&b.path)),
+            FileSort::FinalLines => changes.sort_by(|a, b| {
+                b.final_lines
+                    .cmp(&a.final_lines)
+                    .then_with(|| a.path.cmp(&b.path))
+            }),
+        }
+    }
+}
+
+/// A scrollable, sortable table of changed files.
+struct FileList {
+    changes: Vec<FileChange>,
+    sort: FileSort,
+    state: TableState,
+}
+
+impl FileList {
+    fn new(mut changes: Vec<FileChange>) -> Self {
+        let sort = FileSort::Delta;
+        sort.sort(&mut changes);
+        let mut state = TableState::default();
+        if !changes.is_empty() {
+            state.select(Some(0));
+        }
+        FileList {
+            changes,
+            sort,
+            state,
+        }
+    }
+
+    /// Handles a key press, returning whether the user asked to quit.
+    fn handle_key(&mut self, code: KeyCode) -> bool {
+        let last = self.changes.len().saturating_sub(1);
+        let selected = self.state.selected().unwrap_or(0);
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return true,
+            KeyCode::Down | KeyCode::Char('j') => self.state.select(Some((selected + 1).min(last))),
+            KeyCode::Up | KeyCode::Char('k') => self.state.select(Some(selected.saturating_sub(1))),
+            KeyCode::PageDown => self.state.select(Some((selected + 10).min(last))),
+            KeyCode::PageUp => self.state.select(Some(selected.saturating_sub(10))),
+            KeyCode::Char('s') => {
+                self.sort = self.sort.next();
+                self.sort.sort(&mut self.changes);
+            }
+            _ => {}
+        }
+        false
+    }
+}
+
+/// Width of the table's line count columns.
+const COUNT_COLUMN_WIDTH: u16 = 10;
+/// Three rows of bars inside a border.
+const ACTIVITY_HEIGHT: u16 = 5;
+
+/// Builds the table of changed files for an area `width` columns wide, with
+/// paths shortened in the middle to fit on one line.
+fn file_table<'a>(
+    project_directory: &Path,
+    changes: &[FileChange],
+    sort: FileSort,
+    formatter: &StatsFormatter,
+    width: u16,
+) -> Table<'a> {
+    // Borders, the three count columns and the spacing between columns.
+    let file_width = usize::from(width.saturating_sub(2 + 3 * COUNT_COLUMN_WIDTH + 3));
+    let rows = changes.iter().map(|change| {
+        let color = match change.kind {
+            ChangeKind::Created => Color::Green,
+            ChangeKind::Modified => Color::Yellow,
+            ChangeKind::Deleted => Color::Red,
+            ChangeKind::Renamed => Color::Cyan,
+        };
+        let relative =
+            |path: &'_ Path| formatter.path(path.strip_prefix(project_directory).unwrap_or(path));
+        let path = match &change.renamed_from {
+            Some(from) => format!("{} → {}", relative(from), relative(&change.path)),
+            None => relative(&change.path),
+        };
+        let icon = formatter.change_icon(change.kind);
+        let path = truncate_middle(&path, file_width.saturating_sub(icon.width() + 1));
+        Row::new(vec![
+            Cell::from(format!("{} {}", icon, path)),
+            Cell::from(formatter.count(change.initial_lines)),
+            Cell::from(formatter.count(change.final_lines)),
+            Cell::from(formatter.signed_count(change.delta())),
+        ])
+        .style(Style::default().fg(color))
+    });
+
+    Table::new(
+        rows,
+        [
+            Constraint::Min(20),
+            Constraint::Length(COUNT_COLUMN_WIDTH),
+            Constraint::Length(COUNT_COLUMN_WIDTH),
+            Constraint::Length(COUNT_COLUMN_WIDTH),
+        ],
+    )
+    .header(
+        Row::new(vec!["File", "Before", "After", "Change"])
+            .style(Style::default().add_modifier(Modifier::BOLD)),
+    )
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!("Files (sorted by {})", sort.label())),
+    )
+    .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+}
+
+/// Shortens `text` to at most `width` terminal columns by replacing its
+/// middle with an ellipsis, as in `src/very/…/file.rs`. The end of the text,
+/// normally the file name, is kept whole when it fits. Works on grapheme
+/// clusters, so multi-byte and wide characters are never split.
+fn truncate_middle(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return text.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+
+    let graphemes: Vec<&str> = text.graphemes(true).collect();
+    let budget = width - 1;
+    let file_name = text.rsplit(['/', '\\']).next().unwrap_or(text);
+    let tail_budget = file_name.width().max(budget - budget / 3).min(budget);
+    let head_budget = budget - tail_budget;
+
+    let mut head = String::new();
+    let mut used = 0;
+    for grapheme in &graphemes {
+        if used + grapheme.width() > head_budget {
+            break;
+        }
+        used += grapheme.width();
+        head.push_str(grapheme);
+    }
+
+    // The tail gets whatever the head left over.
+    let tail_budget = budget - used;
+    let mut tail = Vec::new();
+    let mut used = 0;
+    for grapheme in graphemes.iter().rev() {
+        if used + grapheme.width() > tail_budget {
+            break;
+        }
+        used += grapheme.width();
+        tail.push(*grapheme);
+    }
+    tail.reverse();
+
+    format!("{}…{}", head, tail.concat())
+}
+
+fn enter_terminal() -> Result<Terminal<CrosstermBackend<Stdout>>> {
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    Ok(Terminal::new(CrosstermBackend::new(stdout))?)
+}
 
-    let duration_secs = (end_time - session.start_time).num_seconds();
-    let hours = duration_secs / 3600;
-    let minutes = (duration_secs % 3600) / 60;
-    let seconds = duration_secs % 60;
+fn leave_terminal(terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> Result<()> {
+    disable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    )?;
+    terminal.show_cursor()?;
+    Ok(())
+}
 
-    let lines_written = session.lines_written.unwrap_or(0);
-    let line_change_color = if lines_written >= 0 {
+/// Shows the end-of-session stats. Normally this takes over the screen until
+/// a key is pressed; with `inline`, the stats are drawn once into the normal
+/// buffer instead, so that they stay in the scrollback after ego exits.
+pub fn draw_stats(
+    stats: &SessionStats,
+    formatter: &StatsFormatter,
+    per_file: bool,
+    inline: bool,
+) -> Result<()> {
+    let line_change_color = if stats.is_cleanup() {
+        Color::Cyan
+    } else {
         Color::Green
+    };
+    let lines_written = if stats.is_cleanup() {
+        formatter.lines_written(stats)
     } else {
-        Color::Red
+        format!("Lines Written: {}", formatter.lines_written(stats))
     };
 
-    let duration_str = format!("{:02}:{:02}:{:02}", hours, minutes, seconds);
-
-    let stats = vec![
+    let mut lines = vec![
         Line::from(Span::styled(
-            format!("Project Directory: {:?}", session.project_directory),
+            format!(
+                "Project Directory: {}",
+                formatter.path(&stats.project_directory)
+            ),
             Style::default().fg(Color::Yellow),
         )),
         Line::from(Span::styled(
-            format!("Session Duration: {}", duration_str),
-            Style::default().fg(Color::Blue),
+            format!("Session ID: {}", stats.id),
+            Style::default().fg(Color::Gray),
         )),
         Line::from(Span::styled(
-            format!("Initial Line Count: {}", session.initial_line_count),
+            format!(
+                "Started: {}  Ended: {}",
+                formatter.datetime(&stats.start_time),
+                formatter.datetime(&stats.end_time)
+            ),
             Style::default().fg(Color::Gray),
         )),
         Line::from(Span::styled(
             format!(
-                "Final Line Count: {}",
-                session.final_line_count.unwrap_or(0)
+                "Session Duration: {}",
+                formatter.duration(stats.duration_secs)
+            ),
+            Style::default().fg(Color::Blue),
+        )),
+        Line::from(Span::styled(
+            format!(
+                "Active Time: {}  Idle Time: {}{}",
+                formatter.duration(stats.active_secs),
+                formatter.duration(stats.idle_secs()),
+                if stats.paused_secs > 0 {
+                    format!("  Paused Time: {}", formatter.duration(stats.paused_secs))
+                } else {
+                    String::new()
+                }
+            ),
+            Style::default().fg(Color::Blue),
+        )),
+        Line::from(Span::styled(
+            match stats.break_durations.len() {
+                0 => "Breaks: none".to_string(),
+                count => format!(
+                    "Breaks: {} totaling {}, longest {}",
+                    count,
+                    formatter.short_duration(stats.total_break_secs()),
+                    formatter.short_duration(stats.longest_break_secs())
+                ),
+            },
+            Style::default().fg(Color::Blue),
+        )),
+        Line::from(Span::styled(
+            format!(
+                "Initial Line Count: {}",
+                formatter.count(stats.initial_line_count)
             ),
             Style::default().fg(Color::Gray),
         )),
         Line::from(Span::styled(
             format!(
-                "Lines Written: {}{}",
-                if lines_written >= 0 { "+" } else { "" },
-                lines_written
+                "Final Line Count: {}",
+                formatter.count(stats.final_line_count)
             ),
+            Style::default().fg(Color::Gray),
+        )),
+        Line::from(Span::styled(
+            lines_written,
             Style::default().fg(line_change_color),
         )),
-        Line::from(Span::raw("")),
         Line::from(Span::styled(
-            "Press any key to exit.",
-            Style::default().add_modifier(Modifier::ITALIC),
+            format!("Lines per Hour: {}", formatter.rate(stats.lines_per_hour())),
+            Style::default().fg(Color::Gray),
+        )),
+        Line::from(Span::styled(
+            format!(
+                "Files per Active Hour: {}",
+                formatter.rate(stats.files_per_active_hour())
+            ),
+            Style::default().fg(Color::Gray),
         )),
     ];
 
+    if let Some(touched) = stats.files_touched {
+        lines.push(Line::from(Span::styled(
+            format!("Files Worked On: {}", formatter.count(touched as i32)),
+            Style::default().fg(Color::Gray),
+        )));
+    }
+
+    if stats.test_lines_written != 0 {
+        lines.push(Line::from(Span::styled(
+            format!(
+                "Production: {}  Tests: {}",
+                formatter.signed_count(stats.production_lines_written()),
+                formatter.signed_count(stats.test_lines_written)
+            ),
+            Style::default().fg(line_change_color),
+        )));
+    }
+
+    if let Some(comparison) = formatter.history_comparison(stats) {
+        lines.push(Line::from(Span::styled(
+            format!("This Session: {}", comparison),
+            Style::default().fg(Color::Gray),
+        )));
+    }
+
+    if let Some(secs) = stats.time_to_first_edit_secs {
+        lines.push(Line::from(Span::styled(
+            format!("Time to First Edit: {}", formatter.duration(secs)),
+            Style::default().fg(Color::Blue),
+        )));
+    }
+
+    if let Some(ratings) = formatter.ratings(stats.mood, stats.energy) {
+        lines.push(Line::from(Span::styled(
+            ratings,
+            Style::default().fg(Color::Blue),
+        )));
+    }
+
+    if let Some(machine) = &stats.machine {
+        lines.push(Line::from(Span::styled(
+            format!("Machine: {}", formatter.machine(machine)),
+            Style::default().fg(Color::Blue),
+        )));
+    }
+
+    if let (Some(initial), Some(last)) = (stats.initial_prose_words, stats.final_prose_words) {
+        lines.push(Line::from(Span::styled(
+            format!(
+                "Prose Words: {} → {} ({})",
+                formatter.count(initial),
+                formatter.count(last),
+                formatter.signed_count(last - initial)
+            ),
+            Style::default().fg(Color::Gray),
+        )));
+    }
+
+    let changes = [
+        (
+            ChangeKind::Created,
+            "Created",
+            &stats.files_created,
+            Color::Green,
+        ),
+        (
+            ChangeKind::Modified,
+            "Modified",
+            &stats.files_modified,
+            Color::Yellow,
+        ),
+        (
+            ChangeKind::Deleted,
+            "Deleted",
+            &stats.files_deleted,
+            Color::Red,
+        ),
+    ];
+    for (kind, label, files, color) in changes {
+        if per_file || files.is_empty() {
+            continue;
+        }
+        lines.push(Line::from(Span::raw("")));
+        lines.push(Line::from(Span::styled(
+            format!(
+                "{} Files {}: {}",
+                formatter.change_icon(kind),
+                label,
+                files.len()
+            ),
+            Style::default().fg(color).add_modifier(Modifier::BOLD),
+        )));
+        for file in files {
+            lines.push(Line::from(Span::styled(
+                format!("  {}", formatter.path(stats.relative_path(file))),
+                Style::default().fg(color),
+            )));
+        }
+    }
+
+    if !per_file && !stats.files_renamed.is_empty() {
+        lines.push(Line::from(Span::raw("")));
+        lines.push(Line::from(Span::styled(
+            format!(
+                "{} Files Renamed: {}",
+                formatter.change_icon(ChangeKind::Renamed),
+                stats.files_renamed.len()
+            ),
+            Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+        )));
+        for rename in &stats.files_renamed {
+            lines.push(Line::from(Span::styled(
+                format!("  {}", formatter.rename(stats, rename)),
+                Style::default().fg(Color::Cyan),
+            )));
+        }
+    }
+
+    if !per_file && !stats.metrics_shown.is_empty() {
+        lines.push(Line::from(Span::raw("")));
+        lines.push(Line::from(Span::styled(
+            "Metrics:",
+            Style::default()
+                .fg(Color::Blue)
+                .add_modifier(Modifier::BOLD),
+        )));
+        for metric in &stats.metrics_shown {
+            lines.push(Line::from(Span::styled(
+                format!(
+                    "  {}: {}",
+                    metric.label(),
+                    formatter.metric_change(stats, *metric)
+                ),
+                Style::default().fg(Color::Blue),
+            )));
+        }
+    }
+
+    if let Some(metric) = stats.command_metric.as_ref().filter(|_| !per_file) {
+        let changes = metric.changes();
+        if !changes.is_empty() {
+            lines.push(Line::from(Span::raw("")));
+            lines.push(Line::from(Span::styled(
+                format!("{}:", metric.command),
+                Style::default()
+                    .fg(Color::Blue)
+                    .add_modifier(Modifier::BOLD),
+            )));
+            for (name, initial, last) in changes {
+                lines.push(Line::from(Span::styled(
+                    format!(
+                        "  {}: {}",
+                        name,
+                        formatter.command_metric_change(initial, last)
+                    ),
+                    Style::default().fg(Color::Blue),
+                )));
+            }
+        }
+    }
+
+    if let Some(counts) = stats.tokei.as_ref().filter(|_| !per_file) {
+        let languages = counts.languages();
+        if !languages.is_empty() {
+            lines.push(Line::from(Span::raw("")));
+            lines.push(Line::from(Span::styled(
+                "Code (tokei):",
+                Style::default()
+                    .fg(Color::Blue)
+                    .add_modifier(Modifier::BOLD),
+            )));
+            for (language, initial, last) in languages {
+                lines.push(Line::from(Span::styled(
+                    format!(
+                        "  {}: code {} · comments {} · blanks {}",
+                        language,
+                        formatter.count_with_change(initial.code, last.code),
+                        formatter.count_with_change(initial.comments, last.comments),
+                        formatter.count_with_change(initial.blanks, last.blanks)
+                    ),
+                    Style::default().fg(Color::Blue),
+                )));
+            }
+        }
+    }
+
+    if !per_file && !stats.top_terms.is_empty() {
+        lines.push(Line::from(Span::raw("")));
+        lines.push(Line::from(Span::styled(
+            "Top Terms:",
+            Style::default()
+                .fg(Color::Magenta)
+                .add_modifier(Modifier::BOLD),
+        )));
+        lines.push(Line::from(Span::styled(
+            format!("  {}", formatter.top_terms(&stats.top_terms)),
+            Style::default().fg(Color::Magenta),
+        )));
+    }
+
+    let breakdown = stats.breakdown();
+    if !per_file && !breakdown.is_empty() {
+        lines.push(Line::from(Span::raw("")));
+        lines.push(Line::from(Span::styled(
+            format!("{}:", stats.group_by.heading()),
+            Style::default()
+                .fg(Color::Blue)
+                .add_modifier(Modifier::BOLD),
+        )));
+        let width = breakdown
+            .iter()
+            .map(|(group, _)| group.width())
+            .max()
+            .unwrap_or(0);
+        for (group, change) in breakdown {
+            lines.push(Line::from(Span::styled(
+                format!(
+                    "  {}{}  {} files  {} lines  {} chars",
+                    group,
+                    " ".repeat(width - group.width()),
+                    formatter.count(change.files as i32),
+                    formatter.signed_count(change.lines),
+                    formatter.signed_count(change.chars)
+                ),
+                Style::default().fg(Color::Blue),
+            )));
+        }
+    }
+
+    for (title, moves) in [
+        ("Moved Out of Scope", &stats.files_moved_out),
+        ("Moved Into Scope", &stats.files_moved_in),
+    ] {
+        if per_file || moves.is_empty() {
+            continue;
+        }
+        lines.push(Line::from(Span::raw("")));
+        lines.push(Line::from(Span::styled(
+            format!("{}: {}", title, moves.len()),
+            Style::default()
+                .fg(Color::DarkGray)
+                .add_modifier(Modifier::BOLD),
+        )));
+        for moved in moves {
+            lines.push(Line::from(Span::styled(
+                format!("  {}", formatter.rename(stats, moved)),
+                Style::default().fg(Color::DarkGray),
+            )));
+        }
+    }
+
+    for subproject in &stats.subprojects {
+        lines.push(Line::from(Span::raw("")));
+        lines.push(Line::from(Span::styled(
+            format!("Subproject {}:", formatter.path(&subproject.path)),
+            Style::default()
+                .fg(Color::Magenta)
+                .add_modifier(Modifier::BOLD),
+        )));
+        lines.push(Line::from(Span::styled(
+            format!(
+                "  Lines: {} → {} ({})",
+                formatter.count(subproject.initial_line_count),
+                formatter.count(subproject.final_line_count),
+                formatter.signed_count(subproject.lines_written())
+            ),
+            Style::default().fg(Color::Magenta),
+        )));
+        lines.push(Line::from(Span::styled(
+            format!(
+                "  Files Changed: {}",
+                formatter.count(subproject.files_changed as i32)
+            ),
+            Style::default().fg(Color::Magenta),
+        )));
+    }
+
+    if !stats.generated_files_changed.is_empty() {
+        lines.push(Line::from(Span::raw("")));
+        lines.push(Line::from(Span::styled(
+            format!(
+                "Generated Changes: {} files, {} lines",
+                stats.generated_files_changed.len(),
+                formatter.signed_count(stats.generated_lines_written)
+            ),
+            Style::default()
+                .fg(Color::DarkGray)
+                .add_modifier(Modifier::BOLD),
+        )));
+        for file in &stats.generated_files_changed {
+            lines.push(Line::from(Span::styled(
+                format!("  {}", formatter.path(stats.relative_path(file))),
+                Style::default().fg(Color::DarkGray),
+            )));
+        }
+    }
+
+    if stats.files_skipped > 0 {
+        lines.push(Line::from(Span::raw("")));
+        lines.push(Line::from(Span::styled(
+            format!(
+                "Skipped: {} files (too large or binary)",
+                formatter.count(stats.files_skipped as i32)
+            ),
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+
+    if stats.small_files_omitted > 0 {
+        lines.push(Line::from(Span::styled(
+            format!(
+                "Omitted: {} small changed files",
+                formatter.count(stats.small_files_omitted as i32)
+            ),
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+
+    if let Some(comparison) = &stats.branch_comparison {
+        lines.push(Line::from(Span::raw("")));
+        lines.push(Line::from(Span::styled(
+            format!("Compared to {}:", comparison.branch),
+            Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+        )));
+        lines.push(Line::from(Span::styled(
+            format!(
+                "  Lines: +{} / -{}",
+                formatter.count(comparison.lines_added as i32),
+                formatter.count(comparison.lines_removed as i32)
+            ),
+            Style::default().fg(Color::Cyan),
+        )));
+        lines.push(Line::from(Span::styled(
+            format!(
+                "  Files: {} added, {} changed, {} removed",
+                formatter.count(comparison.files_added as i32),
+                formatter.count(comparison.files_changed as i32),
+                formatter.count(comparison.files_removed as i32)
+            ),
+            Style::default().fg(Color::Cyan),
+        )));
+    }
+
+    let active_ratio = stats.active_ratio();
+    let focus_gauge = Gauge::default()
+        .block(Block::default().borders(Borders::ALL).title("Focus"))
+        .gauge_style(Style::default().fg(Color::Green).bg(Color::DarkGray))
+        .ratio(active_ratio)
+        .label(format!(
+            "Active {}% / Idle {}%",
+            formatter.rate(active_ratio * 100.0),
+            formatter.rate((1.0 - active_ratio) * 100.0)
+        ));
+    let mut gauges = vec![focus_gauge];
+    for goal in formatter.goals(stats) {
+        let color = if goal.is_met() {
+            Color::Green
+        } else {
+            Color::Yellow
+        };
+        gauges.push(
+            Gauge::default()
+                .block(Block::default().borders(Borders::ALL).title(goal.title))
+                .gauge_style(Style::default().fg(color).bg(Color::DarkGray))
+                .ratio(goal.ratio.min(1.0))
+                .label(format!("{}: {}", goal.name, goal.progress)),
+        );
+    }
+
+    let mut file_list = FileList::new(stats.file_changes.clone());
+    if inline {
+        return draw_stats_inline(stats, formatter, &gauges, &lines, per_file, &mut file_list);
+    }
+
+    lines.push(Line::from(Span::raw("")));
+    lines.push(Line::from(Span::styled(
+        if per_file {
+            "Up/Down to scroll, s to change sorting, q to exit."
+        } else {
+            "Press any key to exit."
+        },
+        Style::default().add_modifier(Modifier::ITALIC),
+    )));
+
+    let mut terminal = enter_terminal()?;
+    loop {
+        terminal.draw(|f| {
+            render_stats(
+                f,
+                stats,
+                formatter,
+                &gauges,
+                &lines,
+                per_file,
+                &mut file_list,
+            )
+        })?;
+
+        if event::poll(Duration::from_millis(200))? {
+            if let Event::Key(key) = event::read()? {
+                if !per_file {
+                    if key.code != KeyCode::Null {
+                        break;
+                    }
+                    continue;
+                }
+
+                if file_list.handle_key(key.code) {
+                    break;
+                }
+            }
+        }
+    }
+
+    leave_terminal(&mut terminal)
+}
+
+/// Draws the stats once in an inline viewport just tall enough for them,
+/// below the current cursor position.
+fn draw_stats_inline(
+    stats: &SessionStats,
+    formatter: &StatsFormatter,
+    gauges: &[Gauge],
+    lines: &[Line],
+    per_file: bool,
+    file_list: &mut FileList,
+) -> Result<()> {
+    // Borders and margins around the gauges, the text and the file table.
+    let table_height = if per_file {
+        file_list.changes.len() + 3
+    } else {
+        0
+    };
+    let activity_height = if stats.activity_buckets.is_empty() {
+        0
+    } else {
+        ACTIVITY_HEIGHT as usize
+    };
+    let height = 4 + 3 * gauges.len() + activity_height + lines.len() + table_height;
+
+    enable_raw_mode()?;
+    let mut terminal = Terminal::with_options(
+        CrosstermBackend::new(io::stdout()),
+        TerminalOptions {
+            viewport: Viewport::Inline(height as u16),
+        },
+    )?;
+    let result =
+        terminal.draw(|f| render_stats(f, stats, formatter, gauges, lines, per_file, file_list));
+    disable_raw_mode()?;
+    result?;
+    println!();
+    Ok(())
+}
+
+fn render_stats(
+    f: &mut Frame,
+    stats: &SessionStats,
+    formatter: &StatsFormatter,
+    gauges: &[Gauge],
+    lines: &[Line],
+    per_file: bool,
+    file_list: &mut FileList,
+) {
+    let size = f.size();
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("Ego - {}", stats.project_name));
+    f.render_widget(block, size);
+
+    let has_activity = !stats.activity_buckets.is_empty();
+    let constraints: Vec<Constraint> = gauges
+        .iter()
+        .map(|_| Constraint::Length(3))
+        .chain(has_activity.then_some(Constraint::Length(ACTIVITY_HEIGHT)))
+        .chain([
+            Constraint::Length(lines.len() as u16),
+            Constraint::Min(if per_file { 5 } else { 0 }),
+        ])
+        .collect();
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(2)
+        .constraints(constraints)
+        .split(size);
+
+    for (gauge, chunk) in gauges.iter().zip(chunks.iter()) {
+        f.render_widget(gauge.clone(), *chunk);
+    }
+    let mut next = gauges.len();
+    if has_activity {
+        render_activity(f, &stats.activity_buckets, chunks[next]);
+        next += 1;
+    }
+
+    let paragraph = Paragraph::new(lines.to_vec())
+        .alignment(Alignment::Left)
+        .block(Block::default());
+    f.render_widget(paragraph, chunks[next]);
+
+    if per_file {
+        let table = file_table(
+            &stats.project_directory,
+            &file_list.changes,
+            file_list.sort,
+            formatter,
+            chunks[next + 1].width,
+        );
+        f.render_stateful_widget(table, chunks[next + 1], &mut file_list.state);
+    }
+}
+
+/// Draws active minutes per window across the session as a sparkline, with
+/// windows without activity left blank. Sessions too long for one bar per
+/// window get wider windows.
+fn render_activity(f: &mut Frame, buckets: &[u64], area: Rect) {
+    let width = usize::from(area.width.saturating_sub(2)).max(1);
+    let merge = buckets.len().div_ceil(width);
+    let data: Vec<u64> = buckets
+        .chunks(merge)
+        .map(|chunk| chunk.iter().sum())
+        .collect();
+    let window_minutes = stats::ACTIVITY_BUCKET_SECS / 60 * merge as i64;
+    let sparkline = Sparkline::default()
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("Activity per {} minutes", window_minutes)),
+        )
+        .data(&data)
+        .max(window_minutes as u64)
+        .style(Style::default().fg(Color::Green));
+    f.render_widget(sparkline, area);
+}
+
+/// Shows the files changed so far in the active session as a table, like
+/// `git diff --stat`.
+pub fn draw_diff(
+    session: &Session,
+    changes: Vec<FileChange>,
+    formatter: &StatsFormatter,
+) -> Result<()> {
+    let mut terminal = enter_terminal()?;
+    let result = diff_loop(&mut terminal, session, changes, formatter);
+    leave_terminal(&mut terminal)?;
+    result
+}
+
+fn diff_loop(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    session: &Session,
+    changes: Vec<FileChange>,
+    formatter: &StatsFormatter,
+) -> Result<()> {
+    let mut file_list = FileList::new(changes);
+    let (insertions, deletions) = stats::line_totals(&file_list.changes);
+    let summary = Line::from(Span::styled(
+        format!(
+            "{} files changed, {} insertions(+), {} deletions(-). Up/Down to scroll, s to change sorting, q to exit.",
+            formatter.count(file_list.changes.len() as i32),
+            formatter.count(insertions),
+            formatter.count(deletions)
+        ),
+        Style::default().add_modifier(Modifier::ITALIC),
+    ));
+
     loop {
         terminal.draw(|f| {
             let size = f.size();
             let block = Block::default()
                 .borders(Borders::ALL)
-                .title("Ego - Session Stats");
+                .title(format!("Ego - {} (diff)", session.project_name));
             f.render_widget(block, size);
 
             let chunks = Layout::default()
                 .direction(Direction::Vertical)
                 .margin(2)
-                .constraints([Constraint::Length(stats.len() as u16), Constraint::Min(0)].as_ref())
+                .constraints([Constraint::Min(3), Constraint::Length(1)].as_ref())
                 .split(size);
 
-            let paragraph = Paragraph::new(stats.clone())
-                .alignment(Alignment::Left)
-                .block(Block::default());
-            f.render_widget(paragraph, chunks[0]);
+            let table = file_table(
+                &session.project_directory,
+                &file_list.changes,
+                file_list.sort,
+                formatter,
+                chunks[0].width,
+            );
+            f.render_stateful_widget(table, chunks[0], &mut file_list.state);
+            f.render_widget(Paragraph::new(summary.clone()), chunks[1]);
         })?;
 
         if event::poll(Duration::from_millis(200))? {
             if let Event::Key(key) = event::read()? {
-                if key.code != KeyCode::Null {
-                    break;
+                if file_list.handle_key(key.code) {
+                    return Ok(());
                 }
             }
         }
     }
+}
 
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
-    Ok(())
+/// Shows an activity report: the totals, a table per project, a table per
+/// day that can be scrolled, and the heatmap of active minutes.
+pub fn draw_summary(
+    heading: &str,
+    totals: &[String],
+    summary: &Summary,
+    heatmap: &[String],
+    formatter: &StatsFormatter,
+) -> Result<()> {
+    let mut terminal = enter_terminal()?;
+    let result = summary_loop(&mut terminal, heading, totals, summary, heatmap, formatter);
+    leave_terminal(&mut terminal)?;
+    result
+}
+
+fn summary_loop(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    heading: &str,
+    totals: &[String],
+    summary: &Summary,
+    heatmap: &[String],
+    formatter: &StatsFormatter,
+) -> Result<()> {
+    let project_rows = summary.project_rows(formatter);
+    let day_rows = summary.day_rows(formatter);
+    let mut lines: Vec<Line> = vec![Line::from(Span::styled(
+        heading.to_string(),
+        Style::default().fg(Color::Yellow),
+    ))];
+    lines.extend(totals.iter().map(|total| {
+        Line::from(Span::styled(
+            total.clone(),
+            Style::default().fg(Color::Blue),
+        ))
+    }));
+    let heatmap: Vec<Line> = heatmap
+        .iter()
+        .map(|line| {
+            Line::from(Span::styled(
+                line.clone(),
+                Style::default().fg(Color::Green),
+            ))
+        })
+        .collect();
+    let mut days = TableState::default();
+    days.select(Some(0));
+
+    loop {
+        terminal.draw(|f| {
+            let size = f.size();
+            let block = Block::default().borders(Borders::ALL).title("Ego - report");
+            f.render_widget(block, size);
+
+            let mut constraints = vec![Constraint::Length(lines.len() as u16)];
+            if !project_rows.is_empty() {
+                constraints.push(Constraint::Length(project_rows.len() as u16 + 3));
+            }
+            constraints.extend([
+                Constraint::Min(4),
+                Constraint::Length(heatmap.len() as u16),
+                Constraint::Length(1),
+            ]);
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .margin(2)
+                .constraints(constraints)
+                .split(size);
+
+            f.render_widget(Paragraph::new(lines.clone()), chunks[0]);
+            let mut next = 1;
+            if !project_rows.is_empty() {
+                f.render_widget(
+                    summary_table("Projects", &report::PROJECT_HEADER, &project_rows),
+                    chunks[next],
+                );
+                next += 1;
+            }
+            f.render_stateful_widget(
+                summary_table("Days", &report::DAY_HEADER, &day_rows),
+                chunks[next],
+                &mut days,
+            );
+            f.render_widget(Paragraph::new(heatmap.clone()), chunks[next + 1]);
+            f.render_widget(
+                Paragraph::new(Line::from(Span::styled(
+                    "Up/Down to scroll the days, q to exit.",
+                    Style::default().add_modifier(Modifier::ITALIC),
+                ))),
+                chunks[next + 2],
+            );
+        })?;
+
+        if event::poll(Duration::from_millis(200))? {
+            if let Event::Key(key) = event::read()? {
+                let selected = days.selected().unwrap_or(0);
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    KeyCode::Down => {
+                        days.select(Some((selected + 1).min(day_rows.len().saturating_sub(1))))
+                    }
+                    KeyCode::Up => days.select(Some(selected.saturating_sub(1))),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// A table of `rows` under `header`, with every column as wide as its widest
+/// cell.
+fn summary_table<'a>(title: &'a str, header: &[&'a str], rows: &[Vec<String>]) -> Table<'a> {
+    let widths: Vec<Constraint> = (0..header.len())
+        .map(|column| {
+            let width = rows
+                .iter()
+                .map(|row| row[column].width())
+                .chain([header[column].width()])
+                .max()
+                .unwrap_or(0);
+            Constraint::Length(width as u16)
+        })
+        .collect();
+    Table::new(rows.iter().map(|row| Row::new(row.clone())), widths)
+        .header(Row::new(header.to_vec()).style(Style::default().add_modifier(Modifier::BOLD)))
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .column_spacing(2)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+}
+
+fn live_lines(
+    session: &Session,
+    report: &StatusReport,
+    formatter: &StatsFormatter,
+) -> Vec<Line<'static>> {
+    let line_change_color = if report.lines_written >= 0 {
+        Color::Green
+    } else {
+        Color::Red
+    };
+    vec![
+        Line::from(Span::styled(
+            format!(
+                "Project Directory: {}",
+                formatter.path(&report.project_directory)
+            ),
+            Style::default().fg(Color::Yellow),
+        )),
+        Line::from(Span::styled(
+            format!("Session ID: {}", report.id),
+            Style::default().fg(Color::Gray),
+        )),
+        Line::from(Span::styled(
+            format!("Started: {}", formatter.datetime(&report.start_time)),
+            Style::default().fg(Color::Gray),
+        )),
+        Line::from(Span::styled(
+            format!(
+                "Elapsed Time: {}{}",
+                formatter.duration(report.elapsed_secs),
+                if report.paused { " (paused)" } else { "" }
+            ),
+            Style::default().fg(Color::Blue),
+        )),
+        Line::from(Span::styled(
+            format!(
+                "Active Time: {}  Breaks: {}",
+                formatter.duration(report.active_secs),
+                session.breaks.len()
+            ),
+            Style::default().fg(Color::Blue),
+        )),
+        Line::from(Span::styled(
+            match session.last_activity {
+                Some(last) => format!("Last Activity: {}", formatter.datetime(&last)),
+                None => "Last Activity: none recorded".to_string(),
+            },
+            Style::default().fg(Color::Gray),
+        )),
+        Line::from(Span::styled(
+            format!(
+                "Current Line Count: {}",
+                formatter.count(report.current_line_count)
+            ),
+            Style::default().fg(Color::Gray),
+        )),
+        Line::from(Span::styled(
+            format!(
+                "Lines Written: {}",
+                formatter.signed_count(report.lines_written)
+            ),
+            Style::default().fg(line_change_color),
+        )),
+    ]
+}
+
+/// Shows a live, read-only view of the session saved in `file`, re-reading
+/// it every `interval`. Tracking itself is left to whichever process started
+/// the session, which saves its progress periodically. Returns once the user
+/// presses `q` or the session file disappears.
+pub fn watch_stats(file: &Path, formatter: &StatsFormatter, interval: Duration) -> Result<()> {
+    let mut terminal = enter_terminal()?;
+    let result = watch_loop(&mut terminal, file, formatter, interval);
+    leave_terminal(&mut terminal)?;
+    result
+}
+
+fn watch_loop(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    file: &Path,
+    formatter: &StatsFormatter,
+    interval: Duration,
+) -> Result<()> {
+    let mut last_refresh: Option<Instant> = None;
+    let mut view = None;
+
+    loop {
+        if last_refresh.is_none_or(|refreshed| refreshed.elapsed() >= interval) {
+            last_refresh = Some(Instant::now());
+            if !file.exists() {
+                return Ok(());
+            }
+            // A capped active time is shown as capped; a warning printed now
+            // would land on top of the view.
+            match Session::read_file(file) {
+                Ok((session, _)) => {
+                    let (current, _) = session.pending(false)?;
+                    let report = StatusReport::new(&session, &current, Local::now());
+                    view = Some((session, report));
+                }
+                // Another tool may be rewriting the file in place; keep the
+                // last view and read it again on the next refresh.
+                Err(EgoError::CorruptSession { .. }) if view.is_some() => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        if let Some((session, report)) = &view {
+            let durations = stats::split_durations(report.elapsed_secs, report.active_secs);
+            let mut lines = live_lines(session, report, formatter);
+            lines.push(Line::from(Span::raw("")));
+            lines.push(Line::from(Span::styled(
+                format!(
+                    "Refreshing every {}. Press q to exit.",
+                    formatter.short_duration(interval.as_secs() as i64)
+                ),
+                Style::default().add_modifier(Modifier::ITALIC),
+            )));
+            let focus_gauge = Gauge::default()
+                .block(Block::default().borders(Borders::ALL).title("Focus"))
+                .gauge_style(Style::default().fg(Color::Green).bg(Color::DarkGray))
+                .ratio(durations.active_ratio)
+                .label(format!(
+                    "Active {} of {} ({}%)",
+                    formatter.short_duration(durations.active_secs),
+                    formatter.short_duration(durations.total_secs),
+                    formatter.rate(durations.active_ratio * 100.0)
+                ));
+
+            terminal.draw(|f| {
+                let size = f.size();
+                let block = Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!("Ego - {} (live)", session.project_name));
+                f.render_widget(block, size);
+
+                let chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .margin(2)
+                    .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+                    .split(size);
+
+                f.render_widget(focus_gauge, chunks[0]);
+                f.render_widget(Paragraph::new(lines).alignment(Alignment::Left), chunks[1]);
+            })?;
+        }
+
+        if event::poll(Duration::from_millis(200))? {
+            if let Event::Key(key) = event::read()? {
+                if matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks `truncate_middle` at every width up to the text's own, which
+    /// it must never exceed, and that no grapheme was split.
+    fn assert_fits_at_every_width(text: &str) {
+        let graphemes: Vec<&str> = text.graphemes(true).collect();
+        for width in 0..=text.width() {
+            let truncated = truncate_middle(text, width);
+            assert!(
+                truncated.width() <= width,
+                "{:?} is wider than {}",
+                truncated,
+                width
+            );
+            assert!(truncated
+                .graphemes(true)
+                .all(|grapheme| grapheme == "…" || graphemes.contains(&grapheme)));
+        }
+    }
+
+    #[test]
+    fn truncate_middle_keeps_wide_graphemes_whole() {
+        assert_fits_at_every_width("src/模块/组件/文件名.rs");
+        assert_fits_at_every_width("docs/絵文字/👩‍💻🇯🇵.md");
+        assert_eq!(truncate_middle("模块/文件.rs", 8), "…文件.rs");
+    }
+
+    #[test]
+    fn truncate_middle_keeps_combining_marks_with_their_base() {
+        let text = "cafe\u{301}/re\u{301}sume\u{301}.md";
+        assert_fits_at_every_width(text);
+        assert_eq!(truncate_middle(text, 10), "…re\u{301}sume\u{301}.md");
+    }
+
+    #[test]
+    fn truncate_middle_at_tiny_widths() {
+        assert_eq!(truncate_middle("src/main.rs", 0), "");
+        assert_eq!(truncate_middle("src/main.rs", 1), "…");
+        assert_eq!(truncate_middle("", 0), "");
+        assert_eq!(truncate_middle("src/main.rs", 11), "src/main.rs");
+    }
+
+    #[test]
+    fn truncate_middle_cuts_name_wider_than_budget() {
+        assert_eq!(truncate_middle("dir/very_long_file_name.rs", 8), "…name.rs");
+    }
 }